@@ -0,0 +1,279 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::BufRead;
+use std::sync::Arc;
+
+use regex::Regex;
+
+use crate::decode::LineReader;
+use crate::{CountError, Decode, Normalization};
+
+/// キーを書き換える規則
+#[derive(Debug, Clone)]
+pub struct AliasRule {
+    source: String,
+    regex: Option<Regex>,
+    target: String,
+}
+
+impl AliasRule {
+    /// from と一致するキーを target に書き換える規則を作る
+    pub fn exact(from: &str, target: &str) -> Self {
+        AliasRule {
+            source: from.to_string(),
+            regex: None,
+            target: target.to_string(),
+        }
+    }
+
+    /// キー全体が pattern にマッチする場合に target に書き換える規則を作る
+    ///
+    /// target の中の `$1` や `${name}` はキャプチャグループに置き換える。
+    ///
+    /// # Errors
+    /// pattern が正規表現として不正な場合
+    pub fn regex(pattern: &str, target: &str) -> Result<Self, regex::Error> {
+        Ok(AliasRule {
+            source: pattern.to_string(),
+            regex: Some(Regex::new(&format!("^(?:{})$", pattern))?),
+            target: target.to_string(),
+        })
+    }
+
+    /// 書き換える対象のキー、または正規表現
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// 正規表現の規則なら `true`
+    pub fn is_regex(&self) -> bool {
+        self.regex.is_some()
+    }
+
+    /// 書き換え後のキー
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+}
+
+impl PartialEq for AliasRule {
+    fn eq(&self, other: &AliasRule) -> bool {
+        self.source == other.source
+            && self.is_regex() == other.is_regex()
+            && self.target == other.target
+    }
+}
+
+impl Eq for AliasRule {}
+
+impl Hash for AliasRule {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state);
+        self.is_regex().hash(state);
+        self.target.hash(state);
+    }
+}
+
+/// 表記ゆれのあるキーを正規のキーに書き換える規則の一覧
+///
+/// [`Config::aliases`](struct.Config.html#structfield.aliases) に指定すると、
+/// [`Normalization`](struct.Normalization.html) を適用した後のキーを書き換えてから数える。
+/// 書き換えたキーはストップワードの除去や語幹の抽出の対象にしない。
+///
+/// 完全一致の規則の別名にもキーと同じ正規化を適用してから比べる。
+/// 正規表現の規則は正規化した後のキーに対して試すので、正規化した後の形で書く。
+/// 完全一致の規則を正規表現の規則より優先し、正規表現の規則は追加した順に試す。
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use fhiroki_bicycle_book_wordcount::{Aliases, Config, Counter};
+///
+/// let aliases = Aliases::from_tsv(Cursor::new(
+///     "# 別名\t正規のキー\n\
+///      Postgres\tPostgreSQL\n\
+///      /(?i)pg(sql)?/\tPostgreSQL\n",
+/// ))
+/// .unwrap();
+///
+/// let mut counter = Counter::with_config(Config {
+///     aliases,
+///     ..Config::default()
+/// });
+/// counter.feed("Postgres PostgreSQL pg PGSQL");
+/// assert_eq!(counter.get("PostgreSQL"), 4);
+/// assert_eq!(counter.rewrites(), [1, 2]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Aliases {
+    rules: Arc<Vec<AliasRule>>,
+    // 完全一致の規則の別名から規則の番号への索引
+    exact: Arc<HashMap<String, usize>>,
+}
+
+impl Aliases {
+    /// 規則のない一覧を作る
+    pub fn new() -> Self {
+        Aliases::default()
+    }
+
+    /// TSV 形式の規則を読み込んで一覧を作る
+    ///
+    /// # Errors
+    /// [`add_tsv`](#method.add_tsv) と同じ
+    pub fn from_tsv(input: impl BufRead) -> Result<Self, CountError> {
+        let mut aliases = Aliases::new();
+        aliases.add_tsv(input)?;
+        Ok(aliases)
+    }
+
+    /// `別名\t正規のキー` の形式で一行に一つずつ書かれた規則を追加する
+    ///
+    /// 別名を `/` で囲んだ場合は正規表現の規則になる。
+    /// 空行と `#` で始まる行は読み飛ばす。
+    ///
+    /// # Errors
+    /// 読み込みに失敗した場合や、列が足りない行、不正な正規表現がある場合
+    pub fn add_tsv(&mut self, input: impl BufRead) -> Result<(), CountError> {
+        let mut reader = LineReader::new(input, Decode::Strict);
+        loop {
+            let line = match reader.read_line()? {
+                Some(line) => line.into_owned(),
+                None => return Ok(()),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (from, target) = match line.find('\t') {
+                Some(i) if i > 0 && i + 1 < line.len() => (&line[..i], &line[i + 1..]),
+                _ => return Err(reader.syntax_error("expected `alias\\tcanonical`")),
+            };
+            let rule = if from.len() > 2 && from.starts_with('/') && from.ends_with('/') {
+                AliasRule::regex(&from[1..from.len() - 1], target)
+                    .map_err(|e| reader.syntax_error(format!("invalid regex: {}", e)))?
+            } else {
+                AliasRule::exact(from, target)
+            };
+            self.push(rule);
+        }
+    }
+
+    /// 規則を末尾に追加する
+    pub fn push(&mut self, rule: AliasRule) {
+        if !rule.is_regex() {
+            Arc::make_mut(&mut self.exact)
+                .entry(rule.source.clone())
+                .or_insert(self.rules.len());
+        }
+        Arc::make_mut(&mut self.rules).push(rule);
+    }
+
+    /// 追加した順の規則
+    pub fn rules(&self) -> &[AliasRule] {
+        &self.rules
+    }
+
+    /// 規則の数
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// 規則がひとつもなければ `true`
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// 完全一致の規則の別名に normalization を適用した一覧
+    pub(crate) fn normalized(&self, normalization: &Normalization) -> Aliases {
+        if normalization.is_identity() {
+            return self.clone();
+        }
+        let mut exact = HashMap::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            if !rule.is_regex() {
+                let source = normalization.apply(&rule.source).into_owned();
+                exact.entry(source).or_insert(i);
+            }
+        }
+        Aliases {
+            rules: Arc::clone(&self.rules),
+            exact: Arc::new(exact),
+        }
+    }
+
+    /// key を書き換える。書き換えた場合は使った規則の番号と書き換え後のキーを返す
+    pub fn rewrite<'a>(&'a self, key: &str) -> Option<(usize, Cow<'a, str>)> {
+        if let Some(&i) = self.exact.get(key) {
+            return Some((i, Cow::Borrowed(&self.rules[i].target)));
+        }
+        self.rules.iter().enumerate().find_map(|(i, rule)| {
+            let regex = rule.regex.as_ref()?;
+            let captures = regex.captures(key)?;
+            let mut target = String::new();
+            captures.expand(&rule.target, &mut target);
+            Some((i, Cow::Owned(target)))
+        })
+    }
+}
+
+impl PartialEq for Aliases {
+    fn eq(&self, other: &Aliases) -> bool {
+        self.rules == other.rules
+    }
+}
+
+impl Eq for Aliases {}
+
+impl Hash for Aliases {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rules.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Case, Config, Counter};
+    use std::io::Cursor;
+
+    #[test]
+    fn exact_rules_take_precedence() {
+        let aliases = Aliases::from_tsv(Cursor::new("/p.*/\tregex\npg\texact\n")).unwrap();
+        assert_eq!(aliases.rewrite("pg"), Some((1, Cow::Borrowed("exact"))));
+        assert_eq!(aliases.rewrite("pgsql").unwrap().0, 0);
+        assert_eq!(aliases.rewrite("mysql"), None);
+    }
+
+    #[test]
+    fn regex_rules_match_whole_key_and_expand_groups() {
+        let aliases = Aliases::from_tsv(Cursor::new("/v(\\d+)/\tversion-$1\n")).unwrap();
+        assert_eq!(aliases.rewrite("v12").unwrap().1, "version-12");
+        assert_eq!(aliases.rewrite("xv12"), None);
+    }
+
+    #[test]
+    fn exact_sources_are_normalized_like_keys() {
+        let aliases = Aliases::from_tsv(Cursor::new("Postgres\tPostgreSQL\n")).unwrap();
+        let mut counter = Counter::with_config(Config {
+            normalize: Normalization {
+                case: Case::Lower,
+                ..Normalization::default()
+            },
+            aliases,
+            ..Config::default()
+        });
+        counter.feed("Postgres postgres POSTGRES");
+        assert_eq!(counter.get("PostgreSQL"), 3);
+        assert_eq!(counter.rewrites(), [3]);
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_line() {
+        let err = Aliases::from_tsv(Cursor::new("a\tb\nno tab\n")).unwrap_err();
+        assert_eq!(err.line(), 2);
+        let err = Aliases::from_tsv(Cursor::new("/(/\tb\n")).unwrap_err();
+        assert_eq!(err.line(), 1);
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, Write};
+use std::num::NonZeroUsize;
+
+use crate::decode::LineReader;
+use crate::{Aliases, Config, CountError, CountOption, DecodeSummary, StopWords, Tokenizer};
+
+/// 共起の重み付け
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Weighting {
+    /// 窓の中の共起をすべて 1 と数える
+    Uniform,
+    /// 距離 d 離れた共起を 1/d と数える
+    InverseDistance,
+}
+
+/// デフォルトは [`Uniform`](enum.Weighting.html#variant.Uniform)
+impl Default for Weighting {
+    fn default() -> Self {
+        Weighting::Uniform
+    }
+}
+
+/// 窓の中で共起する単語の組の重みを数える
+///
+/// 単語 w から前後 window 語以内にある単語 c について、
+/// (w, c) と (c, w) の両方に重みを足す。
+/// 単語は [`Config::aliases`](struct.Config.html#structfield.aliases) で書き換えてから数え、
+/// ストップワードは語彙に含めず、窓の中の語数にも数えない。
+/// 規則ごとの書き換えた回数は [`rewrites`](#method.rewrites) で分かる。
+/// 単語の切り出しには [`Config::tokenizer`](struct.Config.html#method.tokenizer) を使う。
+///
+/// # Examples
+///
+/// ```
+/// use std::num::NonZeroUsize;
+/// use fhiroki_bicycle_book_wordcount::{Cooccurrence, Weighting};
+///
+/// let window = NonZeroUsize::new(2).unwrap();
+/// let mut cooccur = Cooccurrence::new(window).weighting(Weighting::InverseDistance);
+/// cooccur.feed("a b c");
+/// assert_eq!(cooccur.get("a", "b"), 1.0);
+/// assert_eq!(cooccur.get("c", "a"), 0.5);
+///
+/// let mut vocab = Vec::new();
+/// let mut triples = Vec::new();
+/// cooccur.write_vocab(&mut vocab).unwrap();
+/// cooccur.write_triples(&mut triples).unwrap();
+/// assert_eq!(String::from_utf8(vocab).unwrap(), "a\t1\nb\t1\nc\t1\n");
+/// assert!(String::from_utf8(triples).unwrap().starts_with("1\t2\t1\n1\t3\t0.5\n"));
+/// ```
+pub struct Cooccurrence {
+    config: Config,
+    tokenizer: Box<dyn Tokenizer + Send + Sync>,
+    window: usize,
+    weighting: Weighting,
+    cross_lines: bool,
+    // 正規化を適用した別名とストップワード
+    aliases: Aliases,
+    stopwords: StopWords,
+    rewrites: Vec<usize>,
+    ids: HashMap<String, usize>,
+    words: Vec<(String, usize)>,
+    pairs: HashMap<(usize, usize), f64>,
+    recent: VecDeque<usize>,
+    summary: DecodeSummary,
+}
+
+impl Cooccurrence {
+    /// 前後 window 語以内の共起を [`CountOption::Word`](enum.CountOption.html#variant.Word)
+    /// の単語で数える
+    pub fn new(window: NonZeroUsize) -> Self {
+        Cooccurrence::with_config(Config::from(CountOption::Word), window)
+    }
+
+    /// config に従って単語を切り出し、前後 window 語以内の共起を数える
+    ///
+    /// [`NgramOptions::cross_lines`](struct.NgramOptions.html#structfield.cross_lines)
+    /// が `true` のときは行をまたいだ共起も数える。
+    pub fn with_config(config: Config, window: NonZeroUsize) -> Self {
+        let window = window.get();
+        Cooccurrence {
+            tokenizer: config.tokenizer(),
+            cross_lines: config.ngram.cross_lines,
+            rewrites: vec![0; config.aliases.len()],
+            aliases: config.aliases.normalized(&config.normalize),
+            stopwords: config.stopwords.normalized(&config.normalize),
+            config,
+            window,
+            weighting: Weighting::default(),
+            ids: HashMap::new(),
+            words: Vec::new(),
+            pairs: HashMap::new(),
+            recent: VecDeque::with_capacity(window),
+            summary: DecodeSummary::default(),
+        }
+    }
+
+    /// 重み付けを変える
+    pub fn weighting(mut self, weighting: Weighting) -> Self {
+        self.weighting = weighting;
+        self
+    }
+
+    /// text を `\n` で区切られた行の並びとみなして数える
+    pub fn feed(&mut self, text: &str) {
+        self.recent.clear();
+        for line in text.lines() {
+            self.feed_line(line);
+        }
+    }
+
+    /// input から一行ずつ読み込んで数える
+    ///
+    /// # Errors
+    /// [`Counter::feed_reader`](struct.Counter.html#method.feed_reader) と同じ
+    pub fn feed_reader(&mut self, input: impl BufRead) -> Result<(), CountError> {
+        self.recent.clear();
+        let mut reader = LineReader::new(input, self.config.decode);
+        let result = loop {
+            match reader.read_line() {
+                Ok(Some(line)) => self.feed_line(&line),
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.summary += reader.summary();
+        result
+    }
+
+    fn feed_line(&mut self, line: &str) {
+        if !self.cross_lines {
+            self.recent.clear();
+        }
+        for token in self.tokenizer.tokenize(line) {
+            let text = self.config.normalize.apply_cow(token.text);
+            let text = match self.aliases.rewrite(&text) {
+                Some((rule, alias)) => {
+                    self.rewrites[rule] += 1;
+                    alias.into_owned()
+                }
+                None if self.stopwords.contains(&text) => continue,
+                None => text.into_owned(),
+            };
+            let id = match self.ids.get(&text) {
+                Some(&id) => id,
+                None => {
+                    let id = self.words.len();
+                    self.ids.insert(text.clone(), id);
+                    self.words.push((text, 0));
+                    id
+                }
+            };
+            self.words[id].1 += 1;
+
+            for (i, &context) in self.recent.iter().rev().enumerate() {
+                let weight = match self.weighting {
+                    Weighting::Uniform => 1.0,
+                    Weighting::InverseDistance => 1.0 / (i + 1) as f64,
+                };
+                *self.pairs.entry((id, context)).or_insert(0.0) += weight;
+                *self.pairs.entry((context, id)).or_insert(0.0) += weight;
+            }
+
+            if self.recent.len() == self.window {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(id);
+        }
+    }
+
+    /// word と context の共起の重み
+    pub fn get(&self, word: &str, context: &str) -> f64 {
+        match (self.ids.get(word), self.ids.get(context)) {
+            (Some(&w), Some(&c)) => self.pairs.get(&(w, c)).copied().unwrap_or(0.0),
+            _ => 0.0,
+        }
+    }
+
+    /// 共起を数える設定
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// [`Config::aliases`](struct.Config.html#structfield.aliases) の規則ごとの、
+    /// 単語を書き換えた回数。規則と同じ順に並べる
+    pub fn rewrites(&self) -> &[usize] {
+        &self.rewrites
+    }
+
+    /// これまでに読み込んだ入力のデコードの集計
+    pub fn summary(&self) -> DecodeSummary {
+        self.summary
+    }
+
+    /// 語彙を `単語\t出現回数` の形式で一行ずつ書き出す
+    ///
+    /// 出現回数の降順、同じ場合は単語の昇順に並べる。
+    /// この順番の 1 始まりの行番号が [`write_triples`](#method.write_triples) の単語番号になる。
+    pub fn write_vocab(&self, mut out: impl Write) -> io::Result<()> {
+        for &id in &self.ranked() {
+            let (word, n) = &self.words[id];
+            writeln!(out, "{}\t{}", word, n)?;
+        }
+        Ok(())
+    }
+
+    /// 共起を `単語番号\t文脈の単語番号\t重み` の形式で一行ずつ書き出す
+    ///
+    /// 単語番号は [`write_vocab`](#method.write_vocab) の 1 始まりの行番号で、
+    /// 単語番号の昇順に並べる。
+    pub fn write_triples(&self, mut out: impl Write) -> io::Result<()> {
+        let mut rank = vec![0; self.words.len()];
+        for (r, &id) in self.ranked().iter().enumerate() {
+            rank[id] = r + 1;
+        }
+        let mut triples: Vec<_> = self
+            .pairs
+            .iter()
+            .map(|(&(w, c), &weight)| (rank[w], rank[c], weight))
+            .collect();
+        triples.sort_by_key(|&(w, c, _)| (w, c));
+        for (w, c, weight) in triples {
+            writeln!(out, "{}\t{}\t{}", w, c, weight)?;
+        }
+        Ok(())
+    }
+
+    /// 出現回数の降順、単語の昇順に並べた単語の番号
+    fn ranked(&self) -> Vec<usize> {
+        let mut ids: Vec<_> = (0..self.words.len()).collect();
+        ids.sort_by(|&a, &b| {
+            let (a, b) = (&self.words[a], &self.words[b]);
+            b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))
+        });
+        ids
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::AliasRule;
+
+    fn window(n: usize) -> NonZeroUsize {
+        NonZeroUsize::new(n).unwrap()
+    }
+
+    #[test]
+    fn window_limits_distance() {
+        let mut cooccur = Cooccurrence::new(window(1));
+        cooccur.feed("a b c a");
+        assert_eq!(cooccur.get("a", "b"), 1.0);
+        assert_eq!(cooccur.get("b", "a"), 1.0);
+        assert_eq!(cooccur.get("a", "c"), 1.0);
+        assert_eq!(cooccur.get("b", "c"), 1.0);
+        assert_eq!(cooccur.get("a", "a"), 0.0);
+    }
+
+    #[test]
+    fn lines_are_separate_unless_cross_lines() {
+        let mut cooccur = Cooccurrence::new(window(2));
+        cooccur.feed("a\nb");
+        assert_eq!(cooccur.get("a", "b"), 0.0);
+
+        let mut config = Config::from(CountOption::Word);
+        config.ngram.cross_lines = true;
+        let mut cooccur = Cooccurrence::with_config(config, window(2));
+        cooccur.feed("a\nb");
+        assert_eq!(cooccur.get("a", "b"), 1.0);
+    }
+
+    #[test]
+    fn triples_use_vocab_ranks() {
+        let mut cooccur = Cooccurrence::new(window(1));
+        cooccur.feed("x y y");
+        let mut vocab = Vec::new();
+        let mut triples = Vec::new();
+        cooccur.write_vocab(&mut vocab).unwrap();
+        cooccur.write_triples(&mut triples).unwrap();
+        assert_eq!(String::from_utf8(vocab).unwrap(), "y\t2\nx\t1\n");
+        assert_eq!(
+            String::from_utf8(triples).unwrap(),
+            "1\t1\t2\n1\t2\t1\n2\t1\t1\n"
+        );
+    }
+
+    #[test]
+    fn counts_rewrites_per_rule() {
+        let mut aliases = Aliases::new();
+        aliases.push(AliasRule::exact("colour", "color"));
+        aliases.push(AliasRule::exact("grey", "gray"));
+        let config = Config {
+            aliases,
+            ..Config::from(CountOption::Word)
+        };
+        let mut cooccur = Cooccurrence::with_config(config, window(1));
+        cooccur.feed("colour color colour");
+        assert_eq!(cooccur.rewrites(), [2, 0]);
+        assert_eq!(cooccur.get("color", "color"), 4.0);
+    }
+}
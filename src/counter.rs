@@ -0,0 +1,564 @@
+use std::borrow::{Borrow, Cow};
+use std::collections::hash_map::{self, HashMap};
+use std::fmt;
+use std::hash::Hash;
+use std::io::BufRead;
+use std::mem;
+use std::str;
+use std::sync::Arc;
+
+use crate::decode::{trim_newline, LineReader};
+use crate::ngram::Window;
+use crate::{
+    Aliases, Config, CountError, CountOption, Decode, DecodeSummary, Stemmer, StopWords, Tokenizer,
+    WordTokenizer,
+};
+
+/// 何度も入力を与えて頻度を数え続けられるカウンタ
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use fhiroki_bicycle_book_wordcount::{Counter, CountOption};
+///
+/// let mut counter = Counter::new(CountOption::Word);
+/// counter.feed("aa bb cc bb");
+/// counter.feed_reader(Cursor::new("bb cc")).unwrap();
+///
+/// let mut other = Counter::new(CountOption::Word);
+/// other.feed("dd");
+/// counter.merge(other);
+///
+/// assert_eq!(counter.get("bb"), 3);
+/// assert_eq!(counter.get("zz"), 0);
+/// assert_eq!(counter.total(), 7);
+/// assert_eq!(counter.distinct(), 4);
+/// assert_eq!(counter.most_common(2), vec![("bb", 3), ("cc", 2)]);
+/// ```
+#[derive(Clone)]
+pub struct Counter {
+    config: Config,
+    tokenizer: Arc<dyn Tokenizer + Send + Sync>,
+    window: Option<Window>,
+    stemmer: Option<Stemmer>,
+    // 正規化を適用した別名とストップワード
+    aliases: Aliases,
+    stopwords: StopWords,
+    freqs: HashMap<String, usize>,
+    // 語幹ごとの、語幹にそろえる前の単語の頻度
+    surfaces: HashMap<String, HashMap<String, usize>>,
+    rewrites: Vec<usize>,
+    total: usize,
+    summary: DecodeSummary,
+}
+
+impl Counter {
+    /// option を数える空のカウンタを作る
+    pub fn new(option: CountOption) -> Self {
+        Counter::with_config(Config::from(option))
+    }
+
+    /// 設定を指定して空のカウンタを作る
+    pub fn with_config(config: Config) -> Self {
+        match config.option {
+            CountOption::WordNgram(n) if config.ngram.cross_lines => {
+                let mut counter = Counter::from_parts(config, Arc::new(WordTokenizer::new()));
+                counter.window = Some(Window::new(n.get()));
+                counter
+            }
+            _ => {
+                let tokenizer = config.tokenizer();
+                Counter::from_parts(config, Arc::from(tokenizer))
+            }
+        }
+    }
+
+    /// `config.option` の代わりに tokenizer でトークンを切り出す空のカウンタを作る
+    pub fn with_tokenizer(
+        config: Config,
+        tokenizer: impl Tokenizer + Send + Sync + 'static,
+    ) -> Self {
+        Counter::from_parts(config, Arc::new(tokenizer))
+    }
+
+    fn from_parts(config: Config, tokenizer: Arc<dyn Tokenizer + Send + Sync>) -> Self {
+        let stemmer = match config.option {
+            CountOption::Word | CountOption::UnicodeWord => config.stem,
+            _ => None,
+        };
+        Counter {
+            rewrites: vec![0; config.aliases.len()],
+            aliases: config.aliases.normalized(&config.normalize),
+            stopwords: config.stopwords.normalized(&config.normalize),
+            config,
+            tokenizer,
+            window: None,
+            stemmer,
+            freqs: HashMap::new(),
+            surfaces: HashMap::new(),
+            total: 0,
+            summary: DecodeSummary::default(),
+        }
+    }
+
+    /// カウンタの設定
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// text を `\n` で区切られた行の並びとみなして数える
+    ///
+    /// 行をまたぐ n-gram は text の中でだけつなげる。
+    pub fn feed(&mut self, text: &str) {
+        self.reset_window();
+        for line in text.lines() {
+            self.feed_line(line);
+        }
+    }
+
+    /// input から一行ずつ読み込んで数える
+    ///
+    /// # Errors
+    /// [`try_count_with`](fn.try_count_with.html) と同じ。
+    /// エラーが発生するまでに読み込んだ行は数えられたままになる。
+    pub fn feed_reader(&mut self, input: impl BufRead) -> Result<(), CountError> {
+        self.reset_window();
+        let mut reader = LineReader::new(input, self.config.decode);
+        let result = loop {
+            match reader.read_line() {
+                Ok(Some(line)) => self.feed_line(&line),
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.summary += reader.summary();
+        result
+    }
+
+    fn feed_line(&mut self, line: &str) {
+        let tokenizer = Arc::clone(&self.tokenizer);
+        for token in tokenizer.tokenize(line) {
+            if let Some(key) = self.key(token.text) {
+                // 初めて現れたキーのときだけ String を作る
+                match self.freqs.get_mut(key.as_ref()) {
+                    Some(n) => *n += 1,
+                    None => {
+                        self.freqs.insert(key.into_owned(), 1);
+                    }
+                }
+                self.total += 1;
+            }
+        }
+    }
+
+    /// text を入力全体とみなして数える
+    ///
+    /// 行の区切り方とデコードは [`feed_reader`](#method.feed_reader) と同じだが、
+    /// 一行ずつバッファに読み込まずに text から直接切り出す。
+    ///
+    /// # Errors
+    /// [`feed_reader`](#method.feed_reader) と同じ。エラーの位置は text の先頭から数える。
+    pub fn feed_bytes(&mut self, text: &[u8]) -> Result<(), CountError> {
+        self.feed_lines(text, |counter, line| counter.feed_line(&line))
+    }
+
+    /// text を入力全体とみなして数え、入力の一部をそのまま使えるキーは借用したまま freqs に数える
+    pub(crate) fn feed_borrowed<'a>(
+        &mut self,
+        text: &'a [u8],
+        freqs: &mut HashMap<Cow<'a, str>, usize>,
+    ) -> Result<(), CountError> {
+        self.feed_lines(text, |counter, line| match line {
+            Cow::Borrowed(line) => counter.feed_line_borrowed(line, Some(line), freqs),
+            // 置き換えた行は入力の一部ではないので、キーは借用できない
+            Cow::Owned(line) => counter.feed_line_borrowed(&line, None, freqs),
+        })
+    }
+
+    /// text を行に分けて feed に渡す。UTF-8 として正しい行は text から借用する
+    fn feed_lines<'a>(
+        &mut self,
+        text: &'a [u8],
+        mut feed: impl FnMut(&mut Self, Cow<'a, str>),
+    ) -> Result<(), CountError> {
+        self.reset_window();
+        let mut start = 0;
+        let mut lines = 0;
+        while start < text.len() {
+            let end = text[start..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(text.len(), |i| start + i + 1);
+            let bytes = trim_newline(&text[start..end]);
+            lines += 1;
+            self.summary.lines += 1;
+            match str::from_utf8(bytes) {
+                Ok(line) => feed(self, Cow::Borrowed(line)),
+                Err(e) => match self.config.decode {
+                    Decode::Strict => {
+                        return Err(CountError::InvalidUtf8 {
+                            line: lines,
+                            offset: start + e.valid_up_to(),
+                        })
+                    }
+                    Decode::Lossy => {
+                        self.summary.replaced += 1;
+                        feed(self, String::from_utf8_lossy(bytes));
+                    }
+                    Decode::Skip => self.summary.skipped += 1,
+                },
+            }
+            start = end;
+        }
+        Ok(())
+    }
+
+    /// line を数える。source が line と同じ入力の一部であれば、キーを source から借用する
+    fn feed_line_borrowed<'a>(
+        &mut self,
+        line: &str,
+        source: Option<&'a str>,
+        freqs: &mut HashMap<Cow<'a, str>, usize>,
+    ) {
+        let tokenizer = Arc::clone(&self.tokenizer);
+        for token in tokenizer.tokenize(line) {
+            if let Some(key) = self.key(token.text) {
+                match freqs.get_mut(key.as_ref()) {
+                    Some(n) => *n += 1,
+                    None => {
+                        let key = match source.and_then(|source| subslice(source, &key)) {
+                            Some(key) => Cow::Borrowed(key),
+                            None => Cow::Owned(key.into_owned()),
+                        };
+                        freqs.insert(key, 1);
+                    }
+                }
+                self.total += 1;
+            }
+        }
+    }
+
+    /// トークンを正規化などを適用した数えるキーに変換する。数えない場合は `None`
+    fn key<'t>(&mut self, text: Cow<'t, str>) -> Option<Cow<'t, str>> {
+        let text = self.config.normalize.apply_cow(text);
+        let key = match &mut self.window {
+            Some(window) => Cow::Owned(window.push(&text)?),
+            None => text,
+        };
+        if let Some((rule, alias)) = self.aliases.rewrite(&key) {
+            self.rewrites[rule] += 1;
+            return Some(Cow::Owned(alias.into_owned()));
+        }
+        if self.stopwords.contains(&key) {
+            return None;
+        }
+        match self.stemmer {
+            Some(stemmer) => {
+                let stem = stemmer.stem(&key).into_owned();
+                let surfaces = self.surfaces.entry(stem.clone()).or_default();
+                *surfaces.entry(key.into_owned()).or_insert(0) += 1;
+                Some(Cow::Owned(stem))
+            }
+            None => Some(key),
+        }
+    }
+
+    fn reset_window(&mut self) {
+        if let Some(window) = &mut self.window {
+            window.clear();
+        }
+    }
+
+    /// other の頻度とデコードの集計をこのカウンタに足し合わせる
+    pub fn merge(&mut self, other: Counter) {
+        for (key, n) in other.freqs {
+            *self.freqs.entry(key).or_insert(0) += n;
+        }
+        for (stem, surfaces) in other.surfaces {
+            let entry = self.surfaces.entry(stem).or_default();
+            for (surface, n) in surfaces {
+                *entry.entry(surface).or_insert(0) += n;
+            }
+        }
+        for (n, m) in self.rewrites.iter_mut().zip(other.rewrites) {
+            *n += m;
+        }
+        self.total += other.total;
+        self.summary += other.summary;
+    }
+
+    /// key の頻度。一度も現れていない場合は 0
+    pub fn get(&self, key: &str) -> usize {
+        self.freqs.get(key).copied().unwrap_or(0)
+    }
+
+    /// すべての頻度の合計
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// 異なり数
+    pub fn distinct(&self) -> usize {
+        self.freqs.len()
+    }
+
+    /// 頻度の高い順に最大 n 件を返す。頻度が同じ場合はキーの昇順
+    pub fn most_common(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        entries.truncate(n);
+        entries
+    }
+
+    /// キーと頻度の組を順不同で返すイテレータ
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(self.freqs.iter())
+    }
+
+    /// [`Config::aliases`](struct.Config.html#structfield.aliases) の規則ごとの、
+    /// キーを書き換えた回数。規則と同じ順に並べる
+    pub fn rewrites(&self) -> &[usize] {
+        &self.rewrites
+    }
+
+    /// これまでに読み込んだ入力のデコードの集計
+    pub fn summary(&self) -> DecodeSummary {
+        self.summary
+    }
+
+    /// key を語幹とする単語のうち最も頻度の高いもの。頻度が同じ場合は辞書順で最初のもの
+    ///
+    /// [`Config::stem`](struct.Config.html#structfield.stem) を指定していない場合や、
+    /// key が一度も現れていない場合は key をそのまま返す。
+    pub fn surface<'a>(&'a self, key: &'a str) -> &'a str {
+        self.surfaces
+            .get(key)
+            .and_then(|surfaces| {
+                surfaces
+                    .iter()
+                    .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            })
+            .map_or(key, |(surface, _)| surface)
+    }
+
+    /// 頻度の `HashMap` に変換する
+    pub fn into_map(self) -> HashMap<String, usize> {
+        self.freqs
+    }
+
+    /// キーを [`surface`](#method.surface) に置き換えた頻度の `HashMap` に変換する
+    ///
+    /// 別名に書き換えたキーが語幹の単語と同じになった場合など、
+    /// 複数のキーが同じ単語になった場合は頻度を足し合わせる。
+    /// [`CountOption::Keywords`](enum.CountOption.html#variant.Keywords) の場合は、
+    /// 一度も現れなかった語句も 0 として含める。
+    pub fn into_surface_map(mut self) -> HashMap<String, usize> {
+        let freqs = mem::take(&mut self.freqs);
+        self.surface_keys(freqs)
+    }
+
+    /// freqs のキーを [`surface`](#method.surface) に置き換え、
+    /// [`CountOption::Keywords`](enum.CountOption.html#variant.Keywords) の語句を 0 として加える
+    pub(crate) fn surface_keys<K>(&self, freqs: HashMap<K, usize>) -> HashMap<K, usize>
+    where
+        K: Borrow<str> + From<String> + Eq + Hash,
+    {
+        let mut freqs = if self.surfaces.is_empty() {
+            freqs
+        } else {
+            freqs
+                .into_iter()
+                .fold(HashMap::new(), |mut surfaces, (key, n)| {
+                    let surface = K::from(self.surface(key.borrow()).to_string());
+                    *surfaces.entry(surface).or_insert(0) += n;
+                    surfaces
+                })
+        };
+        if let CountOption::Keywords(keywords) = &self.config.option {
+            for term in keywords.terms() {
+                let term = self.config.normalize.apply(term);
+                freqs.entry(K::from(term.into_owned())).or_insert(0);
+            }
+        }
+        freqs
+    }
+}
+
+/// part が line の一部であれば、line から借用した同じ部分を返す
+fn subslice<'a>(line: &'a str, part: &str) -> Option<&'a str> {
+    let start = (part.as_ptr() as usize).checked_sub(line.as_ptr() as usize)?;
+    line.get(start..start + part.len())
+        .filter(|slice| slice.as_ptr() == part.as_ptr())
+}
+
+impl fmt::Debug for Counter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Counter")
+            .field("config", &self.config)
+            .field("freqs", &self.freqs)
+            .field("total", &self.total)
+            .field("summary", &self.summary)
+            .finish()
+    }
+}
+
+/// [`Counter::iter`](struct.Counter.html#method.iter) が返すイテレータ
+#[derive(Debug, Clone)]
+pub struct Iter<'a>(hash_map::Iter<'a, String, usize>);
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(key, n)| (key.as_str(), *n))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> IntoIterator for &'a Counter {
+    type Item = (&'a str, usize);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for Counter {
+    type Item = (String, usize);
+    type IntoIter = hash_map::IntoIter<String, usize>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.freqs.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AliasRule, LongVowel};
+    use std::io::Cursor;
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn feed_counts_each_line() {
+        let mut counter = Counter::new(CountOption::Line);
+        counter.feed("aa\nbb\r\naa");
+        counter.feed("bb\n");
+        assert_eq!(counter.get("aa"), 2);
+        assert_eq!(counter.get("bb"), 2);
+        assert_eq!(counter.total(), 4);
+        assert_eq!(counter.distinct(), 2);
+    }
+
+    #[test]
+    fn merge_adds_counts_and_summary() {
+        let config = Config {
+            option: CountOption::Char,
+            decode: Decode::Skip,
+            ..Config::default()
+        };
+        let mut a = Counter::with_config(config.clone());
+        a.feed_reader(Cursor::new(&b"ab\n\xff"[..])).unwrap();
+        let mut b = Counter::with_config(config);
+        b.feed_reader(Cursor::new(&b"bc\n\xfe"[..])).unwrap();
+        a.merge(b);
+
+        let mut entries: Vec<_> = a.iter().collect();
+        entries.sort();
+        assert_eq!(entries, [("a", 1), ("b", 2), ("c", 1)]);
+        assert_eq!(a.total(), 4);
+        assert_eq!(a.summary().skipped, 2);
+    }
+
+    #[test]
+    fn with_tokenizer_overrides_option() {
+        let mut counter = Counter::with_tokenizer(Config::default(), crate::CharTokenizer);
+        counter.feed("aba");
+        assert_eq!(counter.get("a"), 2);
+        assert_eq!(counter.distinct(), 2);
+    }
+
+    #[test]
+    fn word_ngrams_cross_lines_only_when_enabled() {
+        let text = "in order\nto be\n";
+        let mut within = Counter::new(CountOption::WordNgram(NonZeroUsize::new(3).unwrap()));
+        within.feed(text);
+        assert_eq!(within.total(), 0);
+
+        let mut config = Config::from(CountOption::WordNgram(NonZeroUsize::new(3).unwrap()));
+        config.ngram.cross_lines = true;
+        let mut across = Counter::with_config(config);
+        across.feed(text);
+        across.feed("in order");
+        assert_eq!(across.get("in order to"), 1);
+        assert_eq!(across.get("order to be"), 1);
+        assert_eq!(across.get("be in order"), 0);
+        assert_eq!(across.total(), 2);
+    }
+
+    #[test]
+    fn long_vowels_are_trimmed_per_word_in_ngrams() {
+        let mut config = Config::from(CountOption::WordNgram(NonZeroUsize::new(2).unwrap()));
+        config.normalize.japanese.long_vowel = LongVowel::Trim;
+        for &cross_lines in &[false, true] {
+            config.ngram.cross_lines = cross_lines;
+            let mut counter = Counter::with_config(config.clone());
+            counter.feed("サーバー サーバー");
+            assert_eq!(
+                counter.get("サーバ サーバ"),
+                1,
+                "cross_lines={}",
+                cross_lines
+            );
+            assert_eq!(counter.total(), 1);
+        }
+    }
+
+    #[test]
+    fn stems_map_back_to_most_frequent_surface() {
+        let config = Config {
+            stem: Some(Stemmer::English),
+            ..Config::default()
+        };
+        let mut counter = Counter::with_config(config.clone());
+        counter.feed("run runs running running");
+        let mut other = Counter::with_config(config);
+        other.feed("runs runs");
+        counter.merge(other);
+
+        assert_eq!(counter.get("run"), 6);
+        assert_eq!(counter.surface("run"), "runs");
+        assert_eq!(counter.surface("unknown"), "unknown");
+        let freqs = counter.into_surface_map();
+        assert_eq!(freqs.len(), 1);
+        assert_eq!(freqs["runs"], 6);
+    }
+
+    #[test]
+    fn alias_target_colliding_with_surface_is_summed() {
+        let mut aliases = Aliases::new();
+        aliases.push(AliasRule::exact("pg", "runs"));
+        let mut counter = Counter::with_config(Config {
+            stem: Some(Stemmer::English),
+            aliases,
+            ..Config::default()
+        });
+        counter.feed("runs runs run pg");
+        let freqs = counter.into_surface_map();
+        assert_eq!(freqs.len(), 1);
+        assert_eq!(freqs["runs"], 4);
+    }
+
+    #[test]
+    fn most_common_breaks_ties_by_key() {
+        let mut counter = Counter::new(CountOption::Word);
+        counter.feed("cc bb aa bb cc dd");
+        assert_eq!(counter.most_common(3), [("bb", 2), ("cc", 2), ("aa", 1)]);
+        assert_eq!(counter.most_common(10).len(), 4);
+    }
+}
@@ -0,0 +1,161 @@
+use std::borrow::Cow;
+use std::io::BufRead;
+use std::ops::AddAssign;
+use std::str;
+
+use crate::CountError;
+
+/// UTF-8 として不正なバイトを含む行の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Decode {
+    /// [`CountError::InvalidUtf8`](enum.CountError.html#variant.InvalidUtf8) を返して中断する
+    Strict,
+    /// `String::from_utf8_lossy` と同じく不正なバイトを U+FFFD に置き換える
+    Lossy,
+    /// 不正なバイトを含む行を読み飛ばす
+    Skip,
+}
+
+/// デフォルトは [`Strict`](enum.Decode.html#variant.Strict)
+impl Default for Decode {
+    fn default() -> Self {
+        Decode::Strict
+    }
+}
+
+/// デコード時に置き換えたり読み飛ばしたりした行の集計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DecodeSummary {
+    /// 読み込んだ行数 (読み飛ばした行も含む)
+    pub lines: usize,
+    /// U+FFFD への置き換えが発生した行数
+    pub replaced: usize,
+    /// 読み飛ばした行数
+    pub skipped: usize,
+}
+
+impl AddAssign for DecodeSummary {
+    fn add_assign(&mut self, other: DecodeSummary) {
+        self.lines += other.lines;
+        self.replaced += other.replaced;
+        self.skipped += other.skipped;
+    }
+}
+
+/// `Decode` に従って input から一行ずつ読み込む
+pub(crate) struct LineReader<R> {
+    input: R,
+    decode: Decode,
+    buf: Vec<u8>,
+    line: usize,
+    start: usize,
+    offset: usize,
+    summary: DecodeSummary,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub(crate) fn new(input: R, decode: Decode) -> Self {
+        LineReader {
+            input,
+            decode,
+            buf: Vec::new(),
+            line: 0,
+            start: 0,
+            offset: 0,
+            summary: DecodeSummary::default(),
+        }
+    }
+
+    pub(crate) fn summary(&self) -> DecodeSummary {
+        self.summary
+    }
+
+    /// 最後に読み込んだ行の書式が不正であることを表すエラーを作る
+    pub(crate) fn syntax_error(&self, message: impl Into<String>) -> CountError {
+        CountError::Syntax {
+            line: self.line,
+            offset: self.start,
+            message: message.into(),
+        }
+    }
+
+    /// 次の行を末尾の改行を取り除いて返す。入力の終わりでは `None` を返す。
+    pub(crate) fn read_line(&mut self) -> Result<Option<Cow<'_, str>>, CountError> {
+        loop {
+            self.buf.clear();
+            let n = self
+                .input
+                .read_until(b'\n', &mut self.buf)
+                .map_err(|source| CountError::Io {
+                    line: self.line + 1,
+                    offset: self.offset,
+                    source,
+                })?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            self.summary.lines += 1;
+            self.start = self.offset;
+            self.offset += n;
+
+            match str::from_utf8(trim_newline(&self.buf)) {
+                Ok(_) => break,
+                Err(e) => match self.decode {
+                    Decode::Strict => {
+                        return Err(CountError::InvalidUtf8 {
+                            line: self.line,
+                            offset: self.start + e.valid_up_to(),
+                        })
+                    }
+                    Decode::Lossy => {
+                        self.summary.replaced += 1;
+                        break;
+                    }
+                    Decode::Skip => self.summary.skipped += 1,
+                },
+            }
+        }
+
+        Ok(Some(String::from_utf8_lossy(trim_newline(&self.buf))))
+    }
+}
+
+/// `BufRead::lines` と同じく末尾の `\n` または `\r\n` を取り除く
+pub(crate) fn trim_newline(buf: &[u8]) -> &[u8] {
+    let buf = buf.strip_suffix(b"\n").unwrap_or(buf);
+    buf.strip_suffix(b"\r").unwrap_or(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read_all(input: &[u8], decode: Decode) -> (Vec<String>, DecodeSummary) {
+        let mut reader = LineReader::new(Cursor::new(input), decode);
+        let mut lines = Vec::new();
+        while let Some(line) = reader.read_line().unwrap() {
+            lines.push(line.into_owned());
+        }
+        (lines, reader.summary())
+    }
+
+    #[test]
+    fn lossy_replaces_invalid_bytes() {
+        let (lines, summary) = read_all(b"aa\nb\xffb\ncc", Decode::Lossy);
+        assert_eq!(lines, ["aa", "b\u{fffd}b", "cc"]);
+        assert_eq!(summary.lines, 3);
+        assert_eq!(summary.replaced, 1);
+        assert_eq!(summary.skipped, 0);
+    }
+
+    #[test]
+    fn skip_drops_invalid_lines() {
+        let (lines, summary) = read_all(b"aa\nb\xffb\r\n\xf0\ncc\r\n", Decode::Skip);
+        assert_eq!(lines, ["aa", "cc"]);
+        assert_eq!(summary.lines, 4);
+        assert_eq!(summary.replaced, 0);
+        assert_eq!(summary.skipped, 2);
+    }
+}
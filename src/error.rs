@@ -0,0 +1,118 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// [`try_count`](fn.try_count.html)などが返すエラー
+#[derive(Debug)]
+pub enum CountError {
+    /// 入力の読み込み中に I/O エラーが発生した
+    Io {
+        /// エラーが発生した行番号 (1 始まり)
+        line: usize,
+        /// エラーが発生した時点までに読み込んだバイト数
+        offset: usize,
+        /// 元の I/O エラー
+        source: io::Error,
+    },
+    /// 入力が UTF-8 としてデコードできなかった
+    InvalidUtf8 {
+        /// 不正なバイトを含む行番号 (1 始まり)
+        line: usize,
+        /// 入力先頭から数えた不正なバイトの位置
+        offset: usize,
+    },
+    /// 辞書などの設定ファイルの書式が不正
+    Syntax {
+        /// 不正な行の行番号 (1 始まり)
+        line: usize,
+        /// 入力先頭から数えた不正な行の先頭位置
+        offset: usize,
+        /// 不正な内容の説明
+        message: String,
+    },
+}
+
+impl CountError {
+    /// エラーが発生した行番号 (1 始まり)
+    pub fn line(&self) -> usize {
+        match *self {
+            CountError::Io { line, .. }
+            | CountError::InvalidUtf8 { line, .. }
+            | CountError::Syntax { line, .. } => line,
+        }
+    }
+
+    /// エラーが発生した入力先頭からのバイト位置
+    pub fn offset(&self) -> usize {
+        match *self {
+            CountError::Io { offset, .. }
+            | CountError::InvalidUtf8 { offset, .. }
+            | CountError::Syntax { offset, .. } => offset,
+        }
+    }
+
+    /// 入力の途中から読み込んだ場合のエラーを、入力全体での位置に直す
+    pub(crate) fn shift(self, lines: usize, offset: usize) -> CountError {
+        match self {
+            CountError::Io {
+                line,
+                offset: o,
+                source,
+            } => CountError::Io {
+                line: line + lines,
+                offset: o + offset,
+                source,
+            },
+            CountError::InvalidUtf8 { line, offset: o } => CountError::InvalidUtf8 {
+                line: line + lines,
+                offset: o + offset,
+            },
+            CountError::Syntax {
+                line,
+                offset: o,
+                message,
+            } => CountError::Syntax {
+                line: line + lines,
+                offset: o + offset,
+                message,
+            },
+        }
+    }
+}
+
+impl fmt::Display for CountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CountError::Io {
+                line,
+                offset,
+                source,
+            } => write!(
+                f,
+                "I/O error at line {} (byte {}): {}",
+                line, offset, source
+            ),
+            CountError::InvalidUtf8 { line, offset } => {
+                write!(f, "invalid UTF-8 at line {} (byte {})", line, offset)
+            }
+            CountError::Syntax {
+                line,
+                offset,
+                message,
+            } => write!(
+                f,
+                "syntax error at line {} (byte {}): {}",
+                line, offset, message
+            ),
+        }
+    }
+}
+
+impl Error for CountError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CountError::Io { source, .. } => Some(source),
+            CountError::InvalidUtf8 { .. } | CountError::Syntax { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::Select;
+
+/// 頻度の書き出し形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// `キー\t頻度` の行。キーの中のタブ、改行、`\` は `\t`、`\n`、`\\` のようにエスケープする
+    Tsv,
+    /// `key,count` のヘッダに続く RFC 4180 形式の行
+    Csv,
+    /// キーから頻度へのひとつの JSON オブジェクト
+    Json,
+    /// `{"key":...,"count":...}` の JSON オブジェクトを一行にひとつずつ並べたもの
+    Ndjson,
+    /// `wc` のように頻度を右寄せでそろえた表
+    Table,
+}
+
+/// デフォルトは [`Tsv`](enum.Format.html#variant.Tsv)
+impl Default for Format {
+    fn default() -> Self {
+        Format::Tsv
+    }
+}
+
+impl Format {
+    /// entries を与えられた順に out に書き出す
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fhiroki_bicycle_book_wordcount::Format;
+    ///
+    /// let entries = [("a\"b", 10), ("c,d", 2)];
+    /// let mut out = Vec::new();
+    /// Format::Csv.write(&mut out, &entries).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "key,count\n\"a\"\"b\",10\n\"c,d\",2\n");
+    ///
+    /// let mut out = Vec::new();
+    /// Format::Table.write(&mut out, &entries).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "10 a\"b\n 2 c,d\n");
+    /// ```
+    pub fn write(self, mut out: impl Write, entries: &[(&str, usize)]) -> io::Result<()> {
+        match self {
+            Format::Tsv => {
+                for (key, n) in entries {
+                    writeln!(out, "{}\t{}", escape_tsv(key), n)?;
+                }
+            }
+            Format::Csv => {
+                writeln!(out, "key,count")?;
+                for (key, n) in entries {
+                    writeln!(out, "{},{}", escape_csv(key), n)?;
+                }
+            }
+            Format::Json => {
+                write!(out, "{{")?;
+                for (i, (key, n)) in entries.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(out, "{}\n  {}: {}", sep, escape_json(key), n)?;
+                }
+                let end = if entries.is_empty() { "" } else { "\n" };
+                writeln!(out, "{}}}", end)?;
+            }
+            Format::Ndjson => {
+                for (key, n) in entries {
+                    writeln!(out, "{{\"key\":{},\"count\":{}}}", escape_json(key), n)?;
+                }
+            }
+            Format::Table => {
+                let width = entries
+                    .iter()
+                    .map(|(_, n)| n.to_string().len())
+                    .max()
+                    .unwrap_or(0);
+                for (key, n) in entries {
+                    writeln!(out, "{:>width$} {}", n, escape_tsv(key), width = width)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 入力ごとの頻度を、入力の名前と与えられた順に out に書き出す
+    ///
+    /// TSV、CSV、NDJSON では先頭に名前の列を加え、JSON では名前から頻度のオブジェクトへの
+    /// オブジェクトにする。表では `==> 名前 <==` の見出しに続けて頻度を書く。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fhiroki_bicycle_book_wordcount::Format;
+    ///
+    /// let a = [("x", 2)];
+    /// let total = [("x", 3), ("y", 1)];
+    /// let mut out = Vec::new();
+    /// Format::Tsv
+    ///     .write_grouped(&mut out, &[("a.txt", &a[..]), ("total", &total[..])])
+    ///     .unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "a.txt\tx\t2\ntotal\tx\t3\ntotal\ty\t1\n");
+    /// ```
+    pub fn write_grouped(
+        self,
+        mut out: impl Write,
+        groups: &[(&str, &[(&str, usize)])],
+    ) -> io::Result<()> {
+        match self {
+            Format::Tsv => {
+                for (name, entries) in groups {
+                    for (key, n) in entries.iter() {
+                        writeln!(out, "{}\t{}\t{}", escape_tsv(name), escape_tsv(key), n)?;
+                    }
+                }
+            }
+            Format::Csv => {
+                writeln!(out, "file,key,count")?;
+                for (name, entries) in groups {
+                    for (key, n) in entries.iter() {
+                        writeln!(out, "{},{},{}", escape_csv(name), escape_csv(key), n)?;
+                    }
+                }
+            }
+            Format::Json => {
+                write!(out, "{{")?;
+                for (i, (name, entries)) in groups.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(out, "{}\n  {}: {{", sep, escape_json(name))?;
+                    for (j, (key, n)) in entries.iter().enumerate() {
+                        let sep = if j == 0 { "" } else { "," };
+                        write!(out, "{}\n    {}: {}", sep, escape_json(key), n)?;
+                    }
+                    let end = if entries.is_empty() { "" } else { "\n  " };
+                    write!(out, "{}}}", end)?;
+                }
+                let end = if groups.is_empty() { "" } else { "\n" };
+                writeln!(out, "{}}}", end)?;
+            }
+            Format::Ndjson => {
+                for (name, entries) in groups {
+                    for (key, n) in entries.iter() {
+                        writeln!(
+                            out,
+                            "{{\"file\":{},\"key\":{},\"count\":{}}}",
+                            escape_json(name),
+                            escape_json(key),
+                            n
+                        )?;
+                    }
+                }
+            }
+            Format::Table => {
+                for (i, (name, entries)) in groups.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(out)?;
+                    }
+                    writeln!(out, "==> {} <==", escape_tsv(name))?;
+                    Format::Table.write(&mut out, entries)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 頻度の降順、同じ場合はキーの昇順に並べる
+///
+/// [`Select`](struct.Select.html) のデフォルトの条件と同じ
+pub fn sort_by_count(freqs: &HashMap<String, usize>) -> Vec<(&str, usize)> {
+    Select::default().apply(freqs)
+}
+
+fn escape_tsv(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+    for c in key.chars() {
+        match c {
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\\' => escaped.push_str("\\\\"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_csv(key: &str) -> String {
+    if key.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", key.replace('"', "\"\""))
+    } else {
+        key.to_string()
+    }
+}
+
+fn escape_json(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len() + 2);
+    escaped.push('"');
+    for c in key.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn written(format: Format, entries: &[(&str, usize)]) -> String {
+        let mut out = Vec::new();
+        format.write(&mut out, entries).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn escapes_special_characters() {
+        let entries = [("a\tb\\", 1), ("\"q\"\n\u{1}", 2)];
+        assert_eq!(
+            written(Format::Tsv, &entries),
+            "a\\tb\\\\\t1\n\"q\"\\n\u{1}\t2\n"
+        );
+        assert_eq!(
+            written(Format::Ndjson, &entries),
+            "{\"key\":\"a\\tb\\\\\",\"count\":1}\n{\"key\":\"\\\"q\\\"\\n\\u0001\",\"count\":2}\n"
+        );
+        assert_eq!(
+            written(Format::Csv, &entries),
+            "key,count\na\tb\\,1\n\"\"\"q\"\"\n\u{1}\",2\n"
+        );
+    }
+
+    #[test]
+    fn json_is_an_object() {
+        assert_eq!(written(Format::Json, &[]), "{}\n");
+        assert_eq!(
+            written(Format::Json, &[("a", 2), ("b", 1)]),
+            "{\n  \"a\": 2,\n  \"b\": 1\n}\n"
+        );
+    }
+
+    #[test]
+    fn grouped_json_nests_objects() {
+        let a = [("x", 1)];
+        let mut out = Vec::new();
+        Format::Json
+            .write_grouped(&mut out, &[("a", &a[..]), ("b", &[][..])])
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\n  \"a\": {\n    \"x\": 1\n  },\n  \"b\": {}\n}\n"
+        );
+    }
+
+    #[test]
+    fn sorted_by_count_then_key() {
+        let freqs: HashMap<_, _> = vec![
+            ("b".to_string(), 1),
+            ("a".to_string(), 1),
+            ("c".to_string(), 2),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(sort_by_count(&freqs), [("c", 2), ("a", 1), ("b", 1)]);
+    }
+}
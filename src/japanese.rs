@@ -0,0 +1,242 @@
+use std::borrow::Cow;
+use unicode_normalization::char::compose;
+
+/// ひらがなとカタカナの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kana {
+    /// そのまま
+    Preserve,
+    /// カタカナをひらがなにそろえる
+    Hiragana,
+    /// ひらがなをカタカナにそろえる
+    Katakana,
+}
+
+/// デフォルトは [`Preserve`](enum.Kana.html#variant.Preserve)
+impl Default for Kana {
+    fn default() -> Self {
+        Kana::Preserve
+    }
+}
+
+/// 長音符の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LongVowel {
+    /// そのまま
+    Preserve,
+    /// かなに続く `-` や `～` などを `ー` にそろえ、連続する `ー` を一つにまとめる
+    Collapse,
+    /// `Collapse` に加えて、4文字以上の語の末尾の `ー` を取り除く (コンピューター → コンピュータ)
+    ///
+    /// 空白を含む n-gram や行では、空白で区切った語ごとに取り除く。
+    Trim,
+}
+
+/// デフォルトは [`Preserve`](enum.LongVowel.html#variant.Preserve)
+impl Default for LongVowel {
+    fn default() -> Self {
+        LongVowel::Preserve
+    }
+}
+
+/// 日本語の表記ゆれの正規化
+///
+/// 全角・半角の統一、ひらがな・カタカナの統一、長音符の統一の順に適用する。
+///
+/// # Examples
+///
+/// ```
+/// use fhiroki_bicycle_book_wordcount::{JapaneseNormalization, Kana, LongVowel};
+///
+/// let ja = JapaneseNormalization {
+///     width: true,
+///     kana: Kana::Katakana,
+///     long_vowel: LongVowel::Trim,
+/// };
+/// assert_eq!(ja.apply("ＡＢＣ１２３"), "ABC123");
+/// assert_eq!(ja.apply("ｶﾀｶﾅｶﾞｲﾄﾞ"), "カタカナガイド");
+/// assert_eq!(ja.apply("こんぴゅーたー"), "コンピュータ");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct JapaneseNormalization {
+    /// `true` のときは全角英数字・記号を半角に、半角カタカナを全角にする
+    pub width: bool,
+    /// ひらがなとカタカナの扱い
+    pub kana: Kana,
+    /// 長音符の扱い
+    pub long_vowel: LongVowel,
+}
+
+/// U+FF61 から U+FF9F までの半角カタカナに対応する全角文字
+const HALFWIDTH_KATAKANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン\u{3099}\u{309a}";
+
+impl JapaneseNormalization {
+    /// 何も変更しない正規化なら `true`
+    pub fn is_identity(&self) -> bool {
+        *self == JapaneseNormalization::default()
+    }
+
+    /// text を正規化する。変更がなければ借用したまま返す
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.is_identity() || text.is_ascii() {
+            return Cow::Borrowed(text);
+        }
+
+        let mut out = String::with_capacity(text.len());
+        // out の中で今の語が始まる位置
+        let mut word = 0;
+        for c in text.chars() {
+            let c = if self.width { narrow(c) } else { c };
+            let c = match self.kana {
+                Kana::Preserve => c,
+                Kana::Hiragana => to_hiragana(c),
+                Kana::Katakana => to_katakana(c),
+            };
+            let prev = out.chars().next_back();
+            match (prev, c) {
+                // 半角カタカナの濁点・半濁点を直前のかなと合成する
+                (Some(p), '\u{3099}') | (Some(p), '\u{309a}') if compose(p, c).is_some() => {
+                    out.pop();
+                    out.push(compose(p, c).unwrap());
+                }
+                (Some(p), c) if self.long_vowel != LongVowel::Preserve && is_long_vowel(c) => {
+                    if is_kana(p) {
+                        out.push('ー');
+                    } else if p != 'ー' {
+                        out.push(c);
+                    }
+                }
+                _ if c.is_whitespace() => {
+                    self.trim(&mut out, word);
+                    out.push(c);
+                    word = out.len();
+                }
+                _ => out.push(c),
+            }
+        }
+        self.trim(&mut out, word);
+
+        if out == text {
+            Cow::Borrowed(text)
+        } else {
+            Cow::Owned(out)
+        }
+    }
+
+    /// `Trim` のときに、out の word から始まる語の末尾の `ー` を取り除く
+    fn trim(&self, out: &mut String, word: usize) {
+        if self.long_vowel == LongVowel::Trim
+            && out.ends_with('ー')
+            && out[word..].chars().count() >= 4
+        {
+            out.pop();
+        }
+    }
+}
+
+/// 全角英数字・記号を半角に、半角カタカナを全角にする
+fn narrow(c: char) -> char {
+    match c {
+        '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap(),
+        '\u{3000}' => ' ',
+        '\u{ff61}'..='\u{ff9f}' => HALFWIDTH_KATAKANA
+            .chars()
+            .nth((c as u32 - 0xff61) as usize)
+            .unwrap(),
+        _ => c,
+    }
+}
+
+fn to_hiragana(c: char) -> char {
+    match c {
+        '\u{30a1}'..='\u{30f6}' | '\u{30fd}'..='\u{30fe}' => {
+            char::from_u32(c as u32 - 0x60).unwrap()
+        }
+        _ => c,
+    }
+}
+
+fn to_katakana(c: char) -> char {
+    match c {
+        '\u{3041}'..='\u{3096}' | '\u{309d}'..='\u{309e}' => {
+            char::from_u32(c as u32 + 0x60).unwrap()
+        }
+        _ => c,
+    }
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{3096}' | '\u{30a1}'..='\u{30fa}')
+}
+
+/// かなに続くときに長音符とみなす文字
+fn is_long_vowel(c: char) -> bool {
+    matches!(
+        c,
+        'ー' | '-'
+            | '\u{2010}'
+            | '\u{2015}'
+            | '\u{2500}'
+            | '\u{301c}'
+            | '\u{ff0d}'
+            | '\u{ff5e}'
+            | '~'
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn halfwidth_table_covers_range() {
+        assert_eq!(HALFWIDTH_KATAKANA.chars().count(), 0xff9f - 0xff61 + 1);
+    }
+
+    #[test]
+    fn width_unifies_alphanumerics_and_katakana() {
+        let ja = JapaneseNormalization {
+            width: true,
+            ..JapaneseNormalization::default()
+        };
+        assert_eq!(ja.apply("ＡＢＣ"), "ABC");
+        assert_eq!(ja.apply("ｶﾀｶﾅ"), "カタカナ");
+        assert_eq!(ja.apply("ﾊﾟﾝﾄﾞﾗ ｳﾞｧ"), "パンドラ ヴァ");
+        assert!(matches!(ja.apply("カタカナ"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn kana_unification() {
+        let hira = JapaneseNormalization {
+            kana: Kana::Hiragana,
+            ..JapaneseNormalization::default()
+        };
+        assert_eq!(hira.apply("カタカナとひらがな"), "かたかなとひらがな");
+        let kata = JapaneseNormalization {
+            kana: Kana::Katakana,
+            ..JapaneseNormalization::default()
+        };
+        assert_eq!(kata.apply("カタカナとひらがな"), "カタカナトヒラガナ");
+    }
+
+    #[test]
+    fn long_vowel_handling() {
+        let collapse = JapaneseNormalization {
+            long_vowel: LongVowel::Collapse,
+            ..JapaneseNormalization::default()
+        };
+        assert_eq!(collapse.apply("サーーバ～"), "サーバー");
+        assert_eq!(collapse.apply("A-B"), "A-B");
+
+        let trim = JapaneseNormalization {
+            long_vowel: LongVowel::Trim,
+            ..JapaneseNormalization::default()
+        };
+        assert_eq!(trim.apply("サーバー"), "サーバ");
+        assert_eq!(trim.apply("キー"), "キー");
+        assert_eq!(
+            trim.apply("サーバー キー コンピューター"),
+            "サーバ キー コンピュータ"
+        );
+    }
+}
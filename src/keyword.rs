@@ -0,0 +1,282 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::BufRead;
+use std::sync::Arc;
+
+use aho_corasick::{AhoCorasick, BuildError};
+
+use crate::decode::LineReader;
+use crate::{CountError, Decode, Token, Tokenizer};
+
+/// 与えられた語句の一覧に一致する部分を Aho-Corasick 法で一度に探して切り出す
+///
+/// トークンのキーは一致した部分ではなく、一覧に書かれたとおりの語句になる。
+/// [`Counter::into_surface_map`](struct.Counter.html#method.into_surface_map) や
+/// [`try_count_with`](fn.try_count_with.html) の結果には、一度も現れなかった語句も 0 として含める。
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use fhiroki_bicycle_book_wordcount::{count, CountOption, Keywords};
+///
+/// let keywords = Keywords::new(&["Rust", "cargo build", "Go"])
+///     .and_then(|keywords| keywords.case_insensitive(true))
+///     .unwrap()
+///     .whole_word(true);
+/// let freq = count(
+///     Cursor::new("rust and RUST\nrun `cargo build`, not rustc"),
+///     CountOption::Keywords(keywords),
+/// );
+/// assert_eq!(freq["Rust"], 2);
+/// assert_eq!(freq["cargo build"], 1);
+/// assert_eq!(freq["Go"], 0);
+/// ```
+#[derive(Clone)]
+pub struct Keywords {
+    terms: Arc<Vec<String>>,
+    automaton: Arc<AhoCorasick>,
+    case_insensitive: bool,
+    whole_word: bool,
+    overlapping: bool,
+}
+
+impl Keywords {
+    /// terms を探すトークナイザを作る。空の語句と重複は取り除く
+    ///
+    /// # Errors
+    /// 語句が多すぎるなどで、探すためのオートマトンが作れない場合
+    pub fn new<S: AsRef<str>>(terms: &[S]) -> Result<Self, BuildError> {
+        let mut seen = HashSet::new();
+        let mut unique = Vec::with_capacity(terms.len());
+        for term in terms {
+            let term = term.as_ref();
+            if !term.is_empty() && seen.insert(term) {
+                unique.push(term.to_string());
+            }
+        }
+        Keywords::from_terms(unique, false)
+    }
+
+    /// 一行に一つずつ書かれた語句の一覧を読み込む
+    ///
+    /// 前後の空白は取り除き、空行と `#` で始まる行は読み飛ばす。
+    ///
+    /// # Errors
+    /// 読み込みに失敗した場合や、UTF-8 として不正な行がある場合は
+    /// [`KeywordsError::Read`](enum.KeywordsError.html#variant.Read)、
+    /// 語句が多すぎるなどでオートマトンが作れない場合は
+    /// [`KeywordsError::Build`](enum.KeywordsError.html#variant.Build)
+    pub fn from_reader(input: impl BufRead) -> Result<Self, KeywordsError> {
+        let mut reader = LineReader::new(input, Decode::Strict);
+        let mut terms = Vec::new();
+        while let Some(line) = reader.read_line()? {
+            let term = line.trim();
+            if !term.starts_with('#') {
+                terms.push(term.to_string());
+            }
+        }
+        Ok(Keywords::new(&terms)?)
+    }
+
+    fn from_terms(terms: Vec<String>, case_insensitive: bool) -> Result<Self, BuildError> {
+        let automaton = AhoCorasick::builder()
+            .ascii_case_insensitive(case_insensitive)
+            .build(&terms)?;
+        Ok(Keywords {
+            terms: Arc::new(terms),
+            automaton: Arc::new(automaton),
+            case_insensitive,
+            whole_word: false,
+            overlapping: false,
+        })
+    }
+
+    /// `true` にすると ASCII の大文字・小文字を区別しない
+    ///
+    /// # Errors
+    /// [`new`](#method.new) と同じ
+    pub fn case_insensitive(self, case_insensitive: bool) -> Result<Self, BuildError> {
+        if case_insensitive == self.case_insensitive {
+            return Ok(self);
+        }
+        Ok(Keywords {
+            whole_word: self.whole_word,
+            overlapping: self.overlapping,
+            ..Keywords::from_terms(self.terms.to_vec(), case_insensitive)?
+        })
+    }
+
+    /// `true` にすると前後が単語の文字 (英数字と `_`) に接している部分は数えない
+    pub fn whole_word(mut self, whole_word: bool) -> Self {
+        self.whole_word = whole_word;
+        self
+    }
+
+    /// `true` にすると重なり合う部分もすべて数える
+    ///
+    /// `false` のときは左から順に、同じ位置では最も長い語句を選び、重なり合う部分は数えない。
+    pub fn overlapping(mut self, overlapping: bool) -> Self {
+        self.overlapping = overlapping;
+        self
+    }
+
+    /// 重複を取り除いた語句の一覧
+    pub fn terms(&self) -> &[String] {
+        &self.terms
+    }
+}
+
+impl Tokenizer for Keywords {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        let mut matches: Vec<_> = self
+            .automaton
+            .find_overlapping_iter(line)
+            .filter(|m| !self.whole_word || is_whole_word(line, m.start(), m.end()))
+            .map(|m| (m.start(), m.end(), m.pattern().as_usize()))
+            .collect();
+        if !self.overlapping {
+            // 開始位置の昇順、長さの降順に並べて、重ならないものを左から選ぶ
+            matches.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)));
+            let mut end = 0;
+            matches.retain(|&(s, e, _)| {
+                let keep = s >= end;
+                if keep {
+                    end = e;
+                }
+                keep
+            });
+        }
+        Box::new(matches.into_iter().map(move |(start, end, i)| Token {
+            text: Cow::Borrowed(self.terms[i].as_str()),
+            span: start..end,
+        }))
+    }
+}
+
+/// line[start..end] の前後が単語の文字に接していなければ `true`
+fn is_whole_word(line: &str, start: usize, end: usize) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    !line[..start].chars().next_back().is_some_and(is_word)
+        && !line[end..].chars().next().is_some_and(is_word)
+}
+
+impl fmt::Debug for Keywords {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Keywords")
+            .field("terms", &self.terms.len())
+            .field("case_insensitive", &self.case_insensitive)
+            .field("whole_word", &self.whole_word)
+            .field("overlapping", &self.overlapping)
+            .finish()
+    }
+}
+
+impl PartialEq for Keywords {
+    fn eq(&self, other: &Keywords) -> bool {
+        self.terms == other.terms
+            && self.case_insensitive == other.case_insensitive
+            && self.whole_word == other.whole_word
+            && self.overlapping == other.overlapping
+    }
+}
+
+impl Eq for Keywords {}
+
+impl Hash for Keywords {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.terms.hash(state);
+        self.case_insensitive.hash(state);
+        self.whole_word.hash(state);
+        self.overlapping.hash(state);
+    }
+}
+
+/// [`Keywords::from_reader`](struct.Keywords.html#method.from_reader) が返すエラー
+#[derive(Debug)]
+pub enum KeywordsError {
+    /// 語句の一覧を読み込めなかった
+    Read(CountError),
+    /// 読み込んだ語句を探すオートマトンが作れなかった。特定の行によるものではない
+    Build(BuildError),
+}
+
+impl From<CountError> for KeywordsError {
+    fn from(e: CountError) -> Self {
+        KeywordsError::Read(e)
+    }
+}
+
+impl From<BuildError> for KeywordsError {
+    fn from(e: BuildError) -> Self {
+        KeywordsError::Build(e)
+    }
+}
+
+impl fmt::Display for KeywordsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeywordsError::Read(e) => e.fmt(f),
+            KeywordsError::Build(e) => write!(f, "cannot build keyword automaton: {}", e),
+        }
+    }
+}
+
+impl Error for KeywordsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KeywordsError::Read(e) => Some(e),
+            KeywordsError::Build(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn keys(keywords: &Keywords, line: &str) -> Vec<String> {
+        keywords
+            .tokenize(line)
+            .map(|t| t.text.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn longest_match_wins_unless_overlapping() {
+        let keywords = Keywords::new(&["new", "new york", "york"]).unwrap();
+        assert_eq!(keys(&keywords, "new york"), ["new york"]);
+        let overlapping = keywords.overlapping(true);
+        assert_eq!(keys(&overlapping, "new york"), ["new", "new york", "york"]);
+    }
+
+    #[test]
+    fn whole_word_falls_back_to_shorter_term() {
+        let keywords = Keywords::new(&["new", "new york"])
+            .unwrap()
+            .whole_word(true);
+        assert_eq!(keys(&keywords, "new yorker"), ["new"]);
+        assert!(keys(&keywords, "renew").is_empty());
+    }
+
+    #[test]
+    fn case_insensitive_reports_listed_term() {
+        let keywords = Keywords::new(&["Rust"]).unwrap();
+        assert!(keys(&keywords, "RUST").is_empty());
+        assert_eq!(
+            keys(&keywords.case_insensitive(true).unwrap(), "RUST rust"),
+            ["Rust", "Rust"]
+        );
+    }
+
+    #[test]
+    fn reader_skips_comments_and_duplicates() {
+        let keywords =
+            Keywords::from_reader(Cursor::new("# terms\nfoo\n\nfoo\nbar baz\n")).unwrap();
+        assert_eq!(keywords.terms(), ["foo", "bar baz"]);
+    }
+}
@@ -1,20 +1,79 @@
 //! wordcount はシンプルな文字、単語、行の出現頻度の計算機能を提供します。
 //! 詳しくは[`count`](fn.count.html)関数のドキュメントを参照してください。
+//! 複数の入力をまたいで数える場合は[`Counter`](struct.Counter.html)を使います。
 #![warn(missing_docs)]
 
-use regex::Regex;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::io::BufRead;
+use std::num::NonZeroUsize;
+
+mod alias;
+mod cooccur;
+mod counter;
+mod decode;
+mod error;
+mod format;
+mod japanese;
+mod keyword;
+mod mapped;
+mod morph;
+mod ngram;
+mod normalize;
+mod parallel;
+mod select;
+mod stem;
+mod stopwords;
+mod tokenizer;
+mod walk;
+
+pub use crate::alias::{AliasRule, Aliases};
+pub use crate::cooccur::{Cooccurrence, Weighting};
+pub use crate::counter::{Counter, Iter};
+pub use crate::decode::{Decode, DecodeSummary};
+pub use crate::error::CountError;
+pub use crate::format::{sort_by_count, Format};
+pub use crate::japanese::{JapaneseNormalization, Kana, LongVowel};
+pub use crate::keyword::{Keywords, KeywordsError};
+pub use crate::mapped::MappedFile;
+pub use crate::morph::{Dictionary, Segmenter};
+pub use crate::ngram::{CharNgramTokenizer, NgramOptions, WordNgramTokenizer};
+pub use crate::normalize::{Case, Form, Normalization};
+pub use crate::parallel::ParallelCounter;
+pub use crate::select::{Order, Select, SortKey};
+pub use crate::stem::Stemmer;
+pub use crate::stopwords::{Language, StopWords};
+pub use crate::tokenizer::{
+    CharTokenizer, GraphemeTokenizer, LineTokenizer, Pattern, Token, Tokenizer,
+    UnicodeWordTokenizer, WordTokenizer,
+};
+pub use crate::walk::{is_binary, Walk, BINARY_CHECK_LEN};
+
+use crate::decode::LineReader;
 
 /// [`count`](fn.count.html)で使うオプション
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum CountOption {
     /// Unicodeの１文字
     Char,
+    /// 連続する n 文字 (文字 n-gram)
+    CharNgram(NonZeroUsize),
+    /// 拡張書記素クラスタ (UAX #29) 単位の見た目上の１文字
+    Grapheme,
     /// 正規表現の \w+ にマッチする単語
     Word,
+    /// 連続する n 個の単語を空白でつないだもの (単語 n-gram)
+    WordNgram(NonZeroUsize),
+    /// Unicode の単語境界 (UAX #29) で区切られた単語
+    UnicodeWord,
     /// \n で区切られた一行
     Line,
+    /// ユーザが指定した正規表現にマッチする部分
+    Pattern(Pattern),
+    /// 辞書に基づく形態素解析で分割した日本語の形態素
+    Morpheme(Segmenter),
+    /// 与えられた語句の一覧に一致する部分
+    Keywords(Keywords),
 }
 
 /// オプションのデフォルトは [`Word`](enum.CountOption.html#variant.Word)
@@ -24,12 +83,82 @@ impl Default for CountOption {
     }
 }
 
+impl CountOption {
+    /// このオプションで頻度を数える対象を切り出すトークナイザ
+    ///
+    /// [`Config`](struct.Config.html) のほかの設定はデフォルトのものを使う。
+    pub fn tokenizer(&self) -> Box<dyn Tokenizer + Send + Sync> {
+        Config::from(self.clone()).tokenizer()
+    }
+}
+
+/// [`try_count_with`](fn.try_count_with.html)で使う設定
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Config {
+    /// 頻度を数える対象
+    pub option: CountOption,
+    /// UTF-8 として不正な行の扱い
+    pub decode: Decode,
+    /// n-gram の窓の区切り方
+    pub ngram: NgramOptions,
+    /// 頻度を数える前にキーに適用する正規化
+    pub normalize: Normalization,
+    /// 正規化した後のキーを正規のキーに書き換える規則
+    pub aliases: Aliases,
+    /// 正規化した後のキーがこれに含まれる場合は数えない
+    pub stopwords: StopWords,
+    /// 単語を語幹にそろえるステマー。ストップワードを取り除いた後に適用する
+    pub stem: Option<Stemmer>,
+}
+
+impl Config {
+    /// `option` で頻度を数える対象を切り出すトークナイザ
+    ///
+    /// このトークナイザは一行ずつ分割するので、
+    /// [`NgramOptions::cross_lines`](struct.NgramOptions.html#structfield.cross_lines)
+    /// は反映されない。
+    pub fn tokenizer(&self) -> Box<dyn Tokenizer + Send + Sync> {
+        use crate::CountOption::*;
+        match &self.option {
+            Char => Box::new(CharTokenizer),
+            CharNgram(n) => Box::new(
+                CharNgramTokenizer::new(n.get())
+                    .reset_at_boundaries(self.ngram.reset_at_boundaries),
+            ),
+            Grapheme => Box::new(GraphemeTokenizer),
+            Word => Box::new(WordTokenizer::new()),
+            WordNgram(n) => Box::new(WordNgramTokenizer::new(n.get())),
+            UnicodeWord => Box::new(UnicodeWordTokenizer),
+            Line => Box::new(LineTokenizer),
+            Pattern(pattern) => Box::new(pattern.clone()),
+            Morpheme(segmenter) => Box::new(segmenter.clone()),
+            Keywords(keywords) => Box::new(keywords.clone()),
+        }
+    }
+}
+
+impl From<CountOption> for Config {
+    fn from(option: CountOption) -> Self {
+        Config {
+            option,
+            ..Config::default()
+        }
+    }
+}
+
 /// input から一行ずつ文字列を読み込み。頻度を数える。
 ///
 /// 頻度を数える対象はオプションによって制御される。
 /// * [`CountOption::Char`](enum.CountOption.html#variant.Char)
+/// * [`CountOption::CharNgram`](enum.CountOption.html#variant.CharNgram)
+/// * [`CountOption::Grapheme`](enum.CountOption.html#variant.Grapheme)
 /// * [`CountOption::Word`](enum.CountOption.html#variant.Word)
+/// * [`CountOption::WordNgram`](enum.CountOption.html#variant.WordNgram)
+/// * [`CountOption::UnicodeWord`](enum.CountOption.html#variant.UnicodeWord)
 /// * [`CountOption::Line`](enum.CountOption.html#variant.Line)
+/// * [`CountOption::Pattern`](enum.CountOption.html#variant.Pattern)
+/// * [`CountOption::Morpheme`](enum.CountOption.html#variant.Morpheme)
+/// * [`CountOption::Keywords`](enum.CountOption.html#variant.Keywords)
 ///
 /// # Examples
 ///
@@ -46,27 +175,132 @@ impl Default for CountOption {
 ///
 ///
 /// # Panics
-/// 入力がUTF-8フォーマットされていない場合
+/// 入力がUTF-8フォーマットされていない場合。
+/// パニックさせたくない場合は [`try_count`](fn.try_count.html) を使う。
 pub fn count(input: impl BufRead, option: CountOption) -> HashMap<String, usize> {
-    let re = Regex::new(r"\w+").unwrap();
+    try_count(input, option).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// [`count`](fn.count.html) と同じく頻度を数えるが、失敗時にパニックせずエラーを返す。
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use fhiroki_bicycle_book_wordcount::{try_count, CountError, CountOption};
+///
+/// let freq = try_count(Cursor::new("aa bb"), CountOption::Word).unwrap();
+/// assert_eq!(freq["aa"], 1);
+///
+/// let err = try_count(Cursor::new(b"aa\nb\xffb"), CountOption::Word).unwrap_err();
+/// match err {
+///     CountError::InvalidUtf8 { line, offset } => assert_eq!((line, offset), (2, 4)),
+///     _ => unreachable!(),
+/// }
+/// ```
+///
+/// # Errors
+/// 入力の読み込みに失敗した場合は [`CountError::Io`](enum.CountError.html#variant.Io)、
+/// 入力がUTF-8フォーマットされていない場合は
+/// [`CountError::InvalidUtf8`](enum.CountError.html#variant.InvalidUtf8) を返す。
+pub fn try_count(
+    input: impl BufRead,
+    option: CountOption,
+) -> Result<HashMap<String, usize>, CountError> {
+    try_count_with(input, &Config::from(option)).map(|(freqs, _)| freqs)
+}
+
+/// 設定に従って頻度を数え、デコード時に置き換えたり読み飛ばしたりした行の集計と共に返す。
+///
+/// [`Config::stem`](struct.Config.html#structfield.stem) を指定した場合、キーは語幹ではなく
+/// その語幹を持つ単語のうち最も頻度の高いものになる
+/// ([`Counter::into_surface_map`](struct.Counter.html#method.into_surface_map) を参照)。
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use fhiroki_bicycle_book_wordcount::{try_count_with, Config, CountOption, Decode};
+///
+/// let config = Config {
+///     option: CountOption::Word,
+///     decode: Decode::Skip,
+///     ..Config::default()
+/// };
+/// let (freq, summary) = try_count_with(Cursor::new(b"aa\nb\xffb\naa"), &config).unwrap();
+/// assert_eq!(freq["aa"], 2);
+/// assert_eq!(summary.skipped, 1);
+/// ```
+///
+/// # Errors
+/// 入力の読み込みに失敗した場合は [`CountError::Io`](enum.CountError.html#variant.Io)、
+/// `config.decode` が [`Decode::Strict`](enum.Decode.html#variant.Strict) で入力が
+/// UTF-8フォーマットされていない場合は
+/// [`CountError::InvalidUtf8`](enum.CountError.html#variant.InvalidUtf8) を返す。
+pub fn try_count_with(
+    input: impl BufRead,
+    config: &Config,
+) -> Result<(HashMap<String, usize>, DecodeSummary), CountError> {
+    let mut counter = Counter::with_config(config.clone());
+    counter.feed_reader(input)?;
+    let summary = counter.summary();
+    Ok((counter.into_surface_map(), summary))
+}
+
+/// text を入力全体として [`try_count_with`](fn.try_count_with.html) と同じく頻度を数えるが、
+/// text の一部をそのまま使えるキーは `String` を作らずに借用したまま返す。
+///
+/// 正規化や別名の書き換え、語幹の抽出などで入力と異なるキーになった場合や、
+/// 不正な UTF-8 を置き換えた行のキーは `Cow::Owned` になる。
+/// ファイルを読み込む場合は [`MappedFile`](struct.MappedFile.html) を使う。
+///
+/// # Examples
+///
+/// ```
+/// use std::borrow::Cow;
+/// use fhiroki_bicycle_book_wordcount::{try_count_borrowed, Config, CountOption};
+///
+/// let text = b"aa bb\naa";
+/// let (freq, summary) = try_count_borrowed(text, &Config::from(CountOption::Word)).unwrap();
+/// assert_eq!(freq["aa"], 2);
+/// assert!(freq.keys().all(|key| matches!(key, Cow::Borrowed(_))));
+/// assert_eq!(summary.lines, 2);
+/// ```
+///
+/// # Errors
+/// [`try_count_with`](fn.try_count_with.html) と同じ
+pub fn try_count_borrowed<'a>(
+    text: &'a [u8],
+    config: &Config,
+) -> Result<(HashMap<Cow<'a, str>, usize>, DecodeSummary), CountError> {
+    let mut counter = Counter::with_config(config.clone());
     let mut freqs = HashMap::new();
+    counter.feed_borrowed(text, &mut freqs)?;
+    let summary = counter.summary();
+    Ok((counter.surface_keys(freqs), summary))
+}
 
-    for line in input.lines() {
-        let line = line.unwrap();
-        use crate::CountOption::*;
-        match option {
-            Char => {
-                for c in line.chars() {
-                    *freqs.entry(c.to_string()).or_insert(0) += 1;
-                }
-            }
-            Word => {
-                for m in re.find_iter(&line) {
-                    let word = m.as_str().to_string();
-                    *freqs.entry(word).or_insert(0) += 1;
-                }
-            }
-            Line => *freqs.entry(line.to_string()).or_insert(0) += 1,
+/// tokenizer で切り出したトークンの頻度を数える。
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use fhiroki_bicycle_book_wordcount::{count_with, WordTokenizer};
+///
+/// let freq = count_with(Cursor::new("aa bb cc bb"), &WordTokenizer::new());
+/// assert_eq!(freq["bb"], 2);
+/// ```
+///
+/// # Panics
+/// 入力がUTF-8フォーマットされていない場合
+pub fn count_with(input: impl BufRead, tokenizer: &impl Tokenizer) -> HashMap<String, usize> {
+    let mut freqs = HashMap::new();
+    let mut reader = LineReader::new(input, Decode::Strict);
+
+    while let Some(line) = reader.read_line().unwrap_or_else(|e| panic!("{}", e)) {
+        for token in tokenizer.tokenize(&line) {
+            *freqs.entry(token.text.into_owned()).or_insert(0) += 1;
         }
     }
 
@@ -118,4 +352,156 @@ mod test {
 
         count(Cursor::new([b'a', 0xf0, 0x90, 0x80]), CountOption::Word);
     }
+
+    #[test]
+    fn try_count_reports_invalid_utf8_position() {
+        let err =
+            try_count(Cursor::new(&b"aa\nbb\ncc\xf0\x90"[..]), CountOption::Word).unwrap_err();
+        match err {
+            CountError::InvalidUtf8 { line, offset } => {
+                assert_eq!(line, 3);
+                assert_eq!(offset, 8);
+            }
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn try_count_reports_io_error() {
+        use std::io::{self, BufReader, Read};
+
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+        }
+
+        let err = try_count(BufReader::new(Broken), CountOption::Line).unwrap_err();
+        assert!(matches!(
+            err,
+            CountError::Io {
+                line: 1,
+                offset: 0,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn try_count_with_lossy_keeps_all_options_working() {
+        let input = &b"a\xffa\nbb"[..];
+        let config = |option| Config {
+            option,
+            decode: Decode::Lossy,
+            ..Config::default()
+        };
+
+        let (freqs, summary) =
+            try_count_with(Cursor::new(input), &config(CountOption::Char)).unwrap();
+        assert_map!(freqs, {"a" => 2, "\u{fffd}" => 1, "b" => 2});
+        assert_eq!(summary.replaced, 1);
+
+        let (freqs, _) = try_count_with(Cursor::new(input), &config(CountOption::Word)).unwrap();
+        assert_map!(freqs, {"a" => 2, "bb" => 1});
+
+        let (freqs, _) = try_count_with(Cursor::new(input), &config(CountOption::Line)).unwrap();
+        assert_map!(freqs, {"a\u{fffd}a" => 1, "bb" => 1});
+    }
+
+    #[test]
+    fn char_ngram_count_works() {
+        let freqs = count(
+            Cursor::new("abab\nab"),
+            CountOption::CharNgram(NonZeroUsize::new(2).unwrap()),
+        );
+        assert_map!(freqs, {"ab" => 3, "ba" => 1});
+        assert_eq!(freqs.len(), 2);
+    }
+
+    #[test]
+    fn grapheme_count_works() {
+        let freqs = count(Cursor::new("cafe\u{301} cafe"), CountOption::Grapheme);
+        assert_map!(freqs, {"e\u{301}" => 1, "e" => 1, "a" => 2});
+    }
+
+    #[test]
+    fn unicode_word_count_works() {
+        let freqs = count(Cursor::new("don't, don't"), CountOption::UnicodeWord);
+        assert_map!(freqs, {"don't" => 2});
+        assert!(!freqs.contains_key("don"));
+    }
+
+    #[test]
+    fn pattern_count_works() {
+        let option = CountOption::Pattern(Pattern::new(r"[A-Za-z']+").unwrap());
+        let freqs = count(Cursor::new("don't stop, don't"), option);
+        assert_map!(freqs, {"don't" => 2, "stop" => 1});
+    }
+
+    #[test]
+    fn normalization_merges_keys() {
+        let config = |option| Config {
+            option,
+            normalize: Normalization {
+                case: Case::Lower,
+                form: Form::Nfc,
+                ..Normalization::default()
+            },
+            ..Config::default()
+        };
+        let input = "The the THE cafe\u{301} caf\u{e9}\nTHE";
+
+        let (freqs, _) = try_count_with(Cursor::new(input), &config(CountOption::Word)).unwrap();
+        assert_map!(freqs, {"the" => 4, "caf\u{e9}" => 2});
+
+        let (freqs, _) = try_count_with(Cursor::new(input), &config(CountOption::Char)).unwrap();
+        assert_map!(freqs, {"t" => 4, "h" => 4});
+
+        let (freqs, _) = try_count_with(Cursor::new(input), &config(CountOption::Line)).unwrap();
+        assert_map!(freqs, {"the" => 1});
+    }
+
+    #[test]
+    fn japanese_normalization_works_with_any_option() {
+        let config = |option| Config {
+            option,
+            normalize: Normalization {
+                japanese: JapaneseNormalization {
+                    width: true,
+                    kana: Kana::Katakana,
+                    ..JapaneseNormalization::default()
+                },
+                ..Normalization::default()
+            },
+            ..Config::default()
+        };
+        let input = "ＡＢＣ ABC ｶﾀｶﾅ かたかな カタカナ";
+
+        let (freqs, _) = try_count_with(Cursor::new(input), &config(CountOption::Word)).unwrap();
+        assert_map!(freqs, {"ABC" => 2, "カタカナ" => 3});
+
+        let (freqs, _) = try_count_with(Cursor::new(input), &config(CountOption::Char)).unwrap();
+        assert_map!(freqs, {"A" => 2, "カ" => 6});
+    }
+
+    #[test]
+    fn stopwords_are_removed_after_normalization() {
+        let mut config = Config {
+            stopwords: StopWords::builtin(Language::English),
+            ..Config::default()
+        };
+        let (freqs, _) = try_count_with(Cursor::new("The cat and the hat"), &config).unwrap();
+        assert_map!(freqs, {"The" => 1, "cat" => 1, "hat" => 1});
+
+        config.normalize.case = Case::Lower;
+        let (freqs, _) = try_count_with(Cursor::new("The cat and the hat"), &config).unwrap();
+        assert_map!(freqs, {"cat" => 1, "hat" => 1});
+    }
+
+    #[test]
+    fn try_count_strips_crlf() {
+        let freqs = try_count(Cursor::new("aa\r\naa\n"), CountOption::Line).unwrap();
+        assert_map!(freqs, {"aa" => 2});
+    }
 }
@@ -1,23 +1,919 @@
-use std::env;
+use std::fmt;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process;
 
-use fhiroki_bicycle_book_wordcount::{count, CountOption};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+
+use fhiroki_bicycle_book_wordcount::{
+    is_binary, Aliases, Case, Config, Cooccurrence, CountError, CountOption, Counter, Decode,
+    DecodeSummary, Dictionary, Form, Format, Kana, Keywords, KeywordsError, Language, LongVowel,
+    NgramOptions, Normalization, Order, ParallelCounter, Pattern, Segmenter, Select, SortKey,
+    Stemmer, StopWords, Walk, Weighting,
+};
+
+// 終了コードは sysexits.h に合わせる
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_IOERR: i32 = 74;
+
+/// Count frequencies of characters, words, lines, n-grams or keywords in files
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    after_help = "Exit status: 0 on success, 64 on usage errors, 65 on invalid input \
+                  (bad UTF-8 or a malformed list file), 66 if a file cannot be opened, \
+                  74 on other I/O errors. Files that cannot be read are skipped with a \
+                  warning and reported in the exit status."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    mode: ModeArgs,
+
+    #[command(flatten)]
+    normalize: NormalizeArgs,
+
+    #[command(flatten)]
+    count: CountArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Export word co-occurrences as a vocabulary file and (word, context, weight) triples
+    Cooccur(CooccurArgs),
+}
+
+#[derive(Debug, Args)]
+struct CooccurArgs {
+    /// Count co-occurrences within N words before and after each word
+    #[arg(long, value_name = "N", help_heading = "Co-occurrence")]
+    window: NonZeroUsize,
+
+    /// Weight co-occurrences by 1/distance
+    #[arg(long, help_heading = "Co-occurrence")]
+    weighted: bool,
+
+    /// Write the vocabulary to FILE
+    #[arg(long, value_name = "FILE", help_heading = "Co-occurrence")]
+    vocab: PathBuf,
+
+    /// Write the triples to FILE
+    #[arg(long, value_name = "FILE", help_heading = "Co-occurrence")]
+    triples: PathBuf,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    mode: ModeArgs,
+
+    #[command(flatten)]
+    normalize: NormalizeArgs,
+}
+
+/// 読み込む入力の指定
+#[derive(Debug, Args)]
+struct InputArgs {
+    /// Files to count; with no FILE, or when FILE is -, read standard input
+    files: Vec<PathBuf>,
+
+    /// Count files in directories recursively
+    #[arg(short, long, help_heading = "Input")]
+    recursive: bool,
+
+    /// Only count files whose path or name matches GLOB (repeatable)
+    #[arg(long, value_name = "GLOB", action = ArgAction::Append, requires = "recursive", help_heading = "Input")]
+    include_glob: Vec<String>,
+
+    /// Skip files and directories whose path or name matches GLOB (repeatable)
+    #[arg(long, value_name = "GLOB", action = ArgAction::Append, requires = "recursive", help_heading = "Input")]
+    exclude_glob: Vec<String>,
+
+    /// Also walk hidden files and directories
+    #[arg(long, requires = "recursive", help_heading = "Input")]
+    hidden: bool,
+
+    /// Do not respect .gitignore and .ignore files
+    #[arg(long, requires = "recursive", help_heading = "Input")]
+    no_ignore: bool,
+
+    /// How to handle lines that are not valid UTF-8
+    #[arg(long, value_enum, default_value_t = DecodeArg::Strict, help_heading = "Input")]
+    decode: DecodeArg,
+}
+
+/// 数える対象の指定
+#[derive(Debug, Args)]
+struct ModeArgs {
+    /// What to count [default: word]
+    #[arg(short, long, value_enum, help_heading = "Mode")]
+    mode: Option<Mode>,
+
+    /// Count runs of N characters or words instead of single ones
+    #[arg(long, value_name = "N", conflicts_with_all = ["pattern", "keywords"], help_heading = "Mode")]
+    ngram: Option<NonZeroUsize>,
+
+    /// Reset character n-grams at whitespace and punctuation
+    #[arg(long, help_heading = "Mode")]
+    ngram_reset: bool,
+
+    /// Let word n-grams and co-occurrence windows span line breaks
+    #[arg(long, help_heading = "Mode")]
+    cross_lines: bool,
+
+    /// Count matches of a regular expression instead of a mode
+    #[arg(long, value_name = "REGEX", conflicts_with_all = ["mode", "keywords"], help_heading = "Mode")]
+    pattern: Option<String>,
+
+    /// Build keys from the pattern's capture groups, e.g. `$1 ${status}`
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        requires = "pattern",
+        help_heading = "Mode"
+    )]
+    capture: Option<String>,
+
+    /// Count only the terms listed in FILE, one per line
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "mode",
+        help_heading = "Mode"
+    )]
+    keywords: Option<PathBuf>,
+
+    /// Match keywords ignoring ASCII case
+    #[arg(long, requires = "keywords", help_heading = "Mode")]
+    ignore_case: bool,
+
+    /// Skip keyword matches inside longer words
+    #[arg(long, requires = "keywords", help_heading = "Mode")]
+    whole_word: bool,
+
+    /// Count overlapping keyword matches
+    #[arg(long, requires = "keywords", help_heading = "Mode")]
+    overlapping: bool,
+
+    /// MeCab CSV dictionary for the morph mode (repeatable)
+    #[arg(long, value_name = "FILE", action = ArgAction::Append, help_heading = "Mode")]
+    dict: Vec<PathBuf>,
+
+    /// MeCab matrix.def connection costs for the morph mode
+    #[arg(long, value_name = "FILE", requires = "dict", help_heading = "Mode")]
+    matrix: Option<PathBuf>,
+
+    /// Keep only morphemes whose part of speech starts with POS (repeatable)
+    #[arg(long, value_name = "POS", action = ArgAction::Append, requires = "dict", help_heading = "Mode")]
+    pos: Vec<String>,
+}
+
+/// キーの正規化の指定
+#[derive(Debug, Args)]
+struct NormalizeArgs {
+    /// Lowercase keys
+    #[arg(long, conflicts_with = "casefold", help_heading = "Normalization")]
+    lowercase: bool,
+
+    /// Apply full Unicode case folding to keys (ß -> ss)
+    #[arg(long, help_heading = "Normalization")]
+    casefold: bool,
+
+    /// Normalize keys to NFC
+    #[arg(long, conflicts_with = "nfkc", help_heading = "Normalization")]
+    nfc: bool,
+
+    /// Normalize keys to NFKC
+    #[arg(long, help_heading = "Normalization")]
+    nfkc: bool,
+
+    /// Remove accents and other combining marks
+    #[arg(long, help_heading = "Normalization")]
+    strip_diacritics: bool,
+
+    /// Unify full-width alphanumerics and half-width katakana
+    #[arg(long, help_heading = "Normalization")]
+    ja_width: bool,
+
+    /// Unify hiragana and katakana
+    #[arg(long, value_enum, value_name = "KANA", help_heading = "Normalization")]
+    ja_kana: Option<KanaArg>,
+
+    /// Unify long vowel marks after kana
+    #[arg(long, value_enum, value_name = "MODE", help_heading = "Normalization")]
+    ja_long_vowel: Option<LongVowelArg>,
+
+    /// Rewrite keys with an `alias<TAB>canonical` file (repeatable)
+    #[arg(long, value_name = "FILE", action = ArgAction::Append, help_heading = "Normalization")]
+    aliases: Vec<PathBuf>,
+
+    /// Drop a built-in stop word list (repeatable)
+    #[arg(long, value_enum, value_name = "LANG", action = ArgAction::Append, help_heading = "Normalization")]
+    stopwords_lang: Vec<LanguageArg>,
+
+    /// Drop the stop words listed in FILE, one per line (repeatable)
+    #[arg(long, value_name = "FILE", action = ArgAction::Append, help_heading = "Normalization")]
+    stopwords: Vec<PathBuf>,
+}
+
+/// 頻度を数えて書き出すときだけの指定
+#[derive(Debug, Args)]
+struct CountArgs {
+    /// Print counts for each file followed by their total
+    #[arg(long, help_heading = "Input")]
+    per_file: bool,
+
+    /// Count with N threads, splitting large files into chunks (0: one per CPU)
+    #[arg(
+        short,
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        help_heading = "Input"
+    )]
+    jobs: usize,
+
+    /// Memory-map files instead of reading them line by line; the files must not
+    /// be modified or truncated while counting
+    #[arg(long, help_heading = "Input")]
+    mmap: bool,
+
+    /// Count words by their Snowball stem
+    #[arg(long, value_enum, value_name = "LANG", help_heading = "Normalization")]
+    stem: Option<StemArg>,
+
+    /// Output format [default: tsv]
+    #[arg(long, value_enum, help_heading = "Output")]
+    format: Option<FormatArg>,
+
+    /// Sort entries by count, key or length [default: count]
+    #[arg(long, value_enum, help_heading = "Output")]
+    sort: Option<SortArg>,
+
+    /// Sort in ascending order (default for key and length)
+    #[arg(long, conflicts_with = "desc", help_heading = "Output")]
+    asc: bool,
+
+    /// Sort in descending order (default for count)
+    #[arg(long, help_heading = "Output")]
+    desc: bool,
+
+    /// Print at most N entries
+    #[arg(long, value_name = "N", help_heading = "Output")]
+    top: Option<usize>,
+
+    /// Print only entries counted at least N times
+    #[arg(long, value_name = "N", help_heading = "Output")]
+    min_count: Option<usize>,
+
+    /// Print only entries counted at most N times
+    #[arg(long, value_name = "N", help_heading = "Output")]
+    max_count: Option<usize>,
+
+    /// Print only keys matching REGEX
+    #[arg(long, value_name = "REGEX", help_heading = "Output")]
+    include: Option<Regex>,
+
+    /// Do not print keys matching REGEX
+    #[arg(long, value_name = "REGEX", help_heading = "Output")]
+    exclude: Option<Regex>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Mode {
+    Char,
+    Grapheme,
+    Word,
+    UnicodeWord,
+    Line,
+    Morph,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DecodeArg {
+    Strict,
+    Lossy,
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum KanaArg {
+    Hiragana,
+    Katakana,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LongVowelArg {
+    Collapse,
+    Trim,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LanguageArg {
+    En,
+    Fr,
+    De,
+    Es,
+    Ja,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum StemArg {
+    En,
+    Fr,
+    De,
+    Es,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum FormatArg {
+    Tsv,
+    Csv,
+    Json,
+    Ndjson,
+    Table,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum SortArg {
+    Count,
+    Key,
+    Length,
+}
+
+/// 終了コード付きのエラー
+#[derive(Debug)]
+struct Failure {
+    code: i32,
+    message: String,
+}
+
+impl Failure {
+    fn usage(message: impl Into<String>) -> Self {
+        Failure {
+            code: EX_USAGE,
+            message: message.into(),
+        }
+    }
+
+    /// path を開くのに失敗した
+    fn open(path: &Path, e: io::Error) -> Self {
+        let code = match e.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied => EX_NOINPUT,
+            _ => EX_IOERR,
+        };
+        Failure {
+            code,
+            message: format!("{}: {}", path.display(), e),
+        }
+    }
+
+    /// path の読み書きに失敗した
+    fn io(path: &Path, e: io::Error) -> Self {
+        Failure {
+            code: EX_IOERR,
+            message: format!("{}: {}", path.display(), e),
+        }
+    }
+
+    /// path の内容から作ったものが使えなかった
+    fn data(path: &Path, e: impl fmt::Display) -> Self {
+        Failure {
+            code: EX_DATAERR,
+            message: format!("{}: {}", path.display(), e),
+        }
+    }
+
+    /// ディレクトリをたどるのに失敗した
+    fn walk(e: ignore::Error) -> Self {
+        let code = match e.io_error().map(io::Error::kind) {
+            Some(io::ErrorKind::NotFound) | Some(io::ErrorKind::PermissionDenied) => EX_NOINPUT,
+            _ => EX_IOERR,
+        };
+        Failure {
+            code,
+            message: e.to_string(),
+        }
+    }
+
+    /// path の読み込みに失敗したか、内容が不正だった
+    fn count(path: &Path, e: CountError) -> Self {
+        let code = match e {
+            CountError::Io { .. } => EX_IOERR,
+            _ => EX_DATAERR,
+        };
+        Failure {
+            code,
+            message: format!("{}: {}", path.display(), e),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.message.fmt(f)
+    }
+}
 
 fn main() {
-    let filename = env::args().nth(1).expect("1 argument FILENAME required");
-    let count_option = env::args().nth(2).expect("2 argument COUNT_OPTION required");
+    let cli = Cli::try_parse().unwrap_or_else(|e| {
+        // --help と --version は標準出力に書いて正常終了する
+        if !e.use_stderr() {
+            let _ = e.print();
+            process::exit(0);
+        }
+        report(e.render().to_string().trim_end());
+        process::exit(EX_USAGE);
+    });
+    match run(cli) {
+        Ok(0) => {}
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            report(&e);
+            process::exit(e.code);
+        }
+    }
+}
+
+/// 診断メッセージをプログラム名を付けて標準エラー出力に書く
+fn report(message: impl fmt::Display) {
+    eprintln!("{}: {}", env!("CARGO_PKG_NAME"), message);
+}
+
+/// 読み込めなかった入力があればその終了コードを、なければ 0 を返す
+fn run(cli: Cli) -> Result<i32, Failure> {
+    match cli.command {
+        Some(Command::Cooccur(args)) => run_cooccur(args),
+        None => run_count(cli),
+    }
+}
+
+/// 共起を数えて語彙と三つ組のファイルに書き出す
+fn run_cooccur(args: CooccurArgs) -> Result<i32, Failure> {
+    let config = config(&args.input, &args.mode, &args.normalize)?;
+
+    // 読み込めなかった入力は警告して飛ばし、最後にその終了コードを返す
+    let mut code = 0;
+    let mut skip = |e: Failure| {
+        report(&e);
+        code = code.max(e.code);
+    };
+    let inputs = inputs(&args.input, &mut skip)?;
 
-    let file = File::open(filename).unwrap();
-    let reader = BufReader::new(&file);
+    let weighting = if args.weighted {
+        Weighting::InverseDistance
+    } else {
+        Weighting::Uniform
+    };
+    let mut cooccur = Cooccurrence::with_config(config, args.window).weighting(weighting);
+    for path in &inputs {
+        // 途中で失敗した入力も、それまでに読み込んだ行は数えられたままになる
+        if let Err(e) = read_input(path, |input| cooccur.feed_reader(input)) {
+            skip(e);
+        }
+    }
+    warn_decode("total", cooccur.summary());
+    report_rewrites(&cooccur.config().aliases, cooccur.rewrites());
+    write_file(&args.vocab, |out| cooccur.write_vocab(out))?;
+    write_file(&args.triples, |out| cooccur.write_triples(out))?;
+    Ok(code)
+}
+
+/// 頻度を数えて標準出力に書き出す
+fn run_count(cli: Cli) -> Result<i32, Failure> {
+    let Cli {
+        input,
+        mode,
+        normalize,
+        count: args,
+        ..
+    } = cli;
+    let mut config = config(&input, &mode, &normalize)?;
+    // ほかの対象では使われずに無視されるオプションは指定できない
+    if args.stem.is_some() && !matches!(config.option, CountOption::Word | CountOption::UnicodeWord)
+    {
+        return Err(Failure::usage(
+            "--stem can only be used with --mode word or --mode unicode-word",
+        ));
+    }
+    if mode.cross_lines && !matches!(config.option, CountOption::WordNgram(_)) {
+        return Err(Failure::usage(
+            "--cross-lines can only be used with --mode word --ngram N",
+        ));
+    }
+    config.stem = args.stem.map(|stem| match stem {
+        StemArg::En => Stemmer::English,
+        StemArg::Fr => Stemmer::French,
+        StemArg::De => Stemmer::German,
+        StemArg::Es => Stemmer::Spanish,
+    });
+
+    // 読み込めなかった入力は警告して飛ばし、最後にその終了コードを返す
+    let mut code = 0;
+    let mut skip = |e: Failure| {
+        report(&e);
+        code = code.max(e.code);
+    };
+    let inputs = inputs(&input, &mut skip)?;
 
-    let option = match count_option.as_str() {
-        "char" => CountOption::Char,
-        "word" => CountOption::Word,
-        "line" => CountOption::Line,
-        _ => panic!("invalid option: select from {char, word, line}")
+    // 標準入力やパイプはバイナリか確かめるために開いたものからその場で数え、
+    // 通常のファイルはバイナリでないことを確かめてからまとめて数える
+    let mut counted = Vec::new();
+    let mut files = Vec::new();
+    for path in &inputs {
+        let regular = path != Path::new("-") && path.is_file();
+        if !regular {
+            let mut counter = Counter::with_config(config.clone());
+            match read_input(path, |input| counter.feed_reader(input)) {
+                Ok(false) => {}
+                Ok(true) => counted.push((path, Some(Ok(counter)))),
+                Err(e) => counted.push((path, Some(Err(e)))),
+            }
+            continue;
+        }
+        match open(path).and_then(|mut input| skip_binary(path, &mut input)) {
+            Ok(true) => {}
+            Ok(false) => {
+                counted.push((path, None));
+                files.push(path);
+            }
+            Err(e) => counted.push((path, Some(Err(e)))),
+        }
+    }
+    let engine = ParallelCounter::new(config.clone()).jobs(args.jobs);
+    // SAFETY: --mmap を指定した利用者が、数えている間にファイルが変わらないことを保証する
+    let engine = unsafe { engine.mmap(args.mmap) };
+    let mut results = engine
+        .count_files(&files)
+        .into_iter()
+        .zip(&files)
+        .map(|(result, path)| result.map_err(|e| Failure::count(path, e)));
+
+    let mut total = Counter::with_config(config);
+    let mut per_file = Vec::new();
+    for (path, result) in counted {
+        // 途中で失敗した入力の頻度は合計に含めない
+        match result.unwrap_or_else(|| results.next().unwrap()) {
+            Ok(counter) => {
+                warn_decode(&input_name(path), counter.summary());
+                if args.per_file {
+                    per_file.push((input_name(path), counter.clone().into_surface_map()));
+                }
+                total.merge(counter);
+            }
+            Err(e) => skip(e),
+        }
+    }
+    report_rewrites(&total.config().aliases, total.rewrites());
+
+    let sort = match args.sort {
+        Some(SortArg::Count) | None => SortKey::Count,
+        Some(SortArg::Key) => SortKey::Key,
+        Some(SortArg::Length) => SortKey::Length,
+    };
+    let select = Select {
+        sort,
+        order: if args.asc {
+            Order::Ascending
+        } else if args.desc {
+            Order::Descending
+        } else {
+            sort.default_order()
+        },
+        top: args.top,
+        min_count: args.min_count,
+        max_count: args.max_count,
+        include: args.include,
+        exclude: args.exclude,
+    };
+    let format = match args.format {
+        Some(FormatArg::Tsv) | None => Format::Tsv,
+        Some(FormatArg::Csv) => Format::Csv,
+        Some(FormatArg::Json) => Format::Json,
+        Some(FormatArg::Ndjson) => Format::Ndjson,
+        Some(FormatArg::Table) => Format::Table,
     };
+    let freqs = total.into_surface_map();
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let result = if args.per_file {
+        let selected: Vec<_> = per_file
+            .iter()
+            .map(|(name, freqs)| (name.as_str(), select.apply(freqs)))
+            .chain(Some(("total", select.apply(&freqs))))
+            .collect();
+        let groups: Vec<_> = selected
+            .iter()
+            .map(|(name, entries)| (*name, &entries[..]))
+            .collect();
+        format.write_grouped(&mut out, &groups)
+    } else {
+        format.write(&mut out, &select.apply(&freqs))
+    };
+    match result.and_then(|()| out.flush()) {
+        // `| head` などで読み手が先に終了した場合は何も言わずに終える
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        result => result.map_err(|e| Failure::io(Path::new("<stdout>"), e))?,
+    }
+    Ok(code)
+}
+
+/// 数える対象、デコード、正規化の指定から設定を作る
+fn config(
+    input: &InputArgs,
+    mode: &ModeArgs,
+    normalize: &NormalizeArgs,
+) -> Result<Config, Failure> {
+    Ok(Config {
+        option: count_option(mode)?,
+        decode: match input.decode {
+            DecodeArg::Strict => Decode::Strict,
+            DecodeArg::Lossy => Decode::Lossy,
+            DecodeArg::Skip => Decode::Skip,
+        },
+        ngram: NgramOptions {
+            reset_at_boundaries: mode.ngram_reset,
+            cross_lines: mode.cross_lines,
+        },
+        normalize: normalization(normalize),
+        aliases: load_aliases(&normalize.aliases)?,
+        stopwords: load_stopwords(&normalize.stopwords_lang, &normalize.stopwords)?,
+        stem: None,
+    })
+}
+
+/// 数える対象を決める
+fn count_option(args: &ModeArgs) -> Result<CountOption, Failure> {
+    // 使われずに無視されるオプションは指定できない
+    if !args.dict.is_empty() && !matches!(args.mode, Some(Mode::Morph)) {
+        return Err(Failure::usage("--dict can only be used with --mode morph"));
+    }
+    if args.ngram_reset && !(matches!(args.mode, Some(Mode::Char)) && args.ngram.is_some()) {
+        return Err(Failure::usage(
+            "--ngram-reset can only be used with --mode char --ngram N",
+        ));
+    }
+
+    if let Some(path) = &args.keywords {
+        let keywords = Keywords::from_reader(open(path)?)
+            .map_err(|e| match e {
+                KeywordsError::Read(e) => Failure::count(path, e),
+                KeywordsError::Build(e) => Failure::data(path, e),
+            })?
+            .case_insensitive(args.ignore_case)
+            .map_err(|e| Failure::data(path, e))?;
+        return Ok(CountOption::Keywords(
+            keywords
+                .whole_word(args.whole_word)
+                .overlapping(args.overlapping),
+        ));
+    }
+    if let Some(pattern) = &args.pattern {
+        let pattern = match &args.capture {
+            Some(capture) => Pattern::with_template(pattern, capture),
+            None => Pattern::new(pattern),
+        };
+        return pattern
+            .map(CountOption::Pattern)
+            .map_err(|e| Failure::usage(format!("invalid pattern: {}", e)));
+    }
+
+    let mode = args.mode.unwrap_or(Mode::Word);
+    if args.ngram.is_some() && !matches!(mode, Mode::Char | Mode::Word) {
+        return Err(Failure::usage(
+            "--ngram can only be used with --mode char or --mode word",
+        ));
+    }
+    let ngram = args.ngram;
+    let option = match mode {
+        Mode::Char => ngram.map_or(CountOption::Char, CountOption::CharNgram),
+        Mode::Grapheme => CountOption::Grapheme,
+        Mode::Word => ngram.map_or(CountOption::Word, CountOption::WordNgram),
+        Mode::UnicodeWord => CountOption::UnicodeWord,
+        Mode::Line => CountOption::Line,
+        Mode::Morph => CountOption::Morpheme(load_segmenter(&args.dict, &args.matrix, &args.pos)?),
+    };
+    Ok(option)
+}
+
+fn normalization(args: &NormalizeArgs) -> Normalization {
+    let mut normalize = Normalization::default();
+    if args.lowercase {
+        normalize.case = Case::Lower;
+    }
+    if args.casefold {
+        normalize.case = Case::Fold;
+    }
+    if args.nfc {
+        normalize.form = Form::Nfc;
+    }
+    if args.nfkc {
+        normalize.form = Form::Nfkc;
+    }
+    normalize.strip_diacritics = args.strip_diacritics;
+    normalize.japanese.width = args.ja_width;
+    normalize.japanese.kana = match args.ja_kana {
+        Some(KanaArg::Hiragana) => Kana::Hiragana,
+        Some(KanaArg::Katakana) => Kana::Katakana,
+        None => Kana::Preserve,
+    };
+    normalize.japanese.long_vowel = match args.ja_long_vowel {
+        Some(LongVowelArg::Collapse) => LongVowel::Collapse,
+        Some(LongVowelArg::Trim) => LongVowel::Trim,
+        None => LongVowel::Preserve,
+    };
+    normalize
+}
+
+fn load_aliases(paths: &[PathBuf]) -> Result<Aliases, Failure> {
+    let mut aliases = Aliases::new();
+    for path in paths {
+        aliases
+            .add_tsv(open(path)?)
+            .map_err(|e| Failure::count(path, e))?;
+    }
+    Ok(aliases)
+}
+
+fn load_stopwords(languages: &[LanguageArg], paths: &[PathBuf]) -> Result<StopWords, Failure> {
+    let mut stopwords = StopWords::new();
+    for language in languages {
+        stopwords.add_builtin(match language {
+            LanguageArg::En => Language::English,
+            LanguageArg::Fr => Language::French,
+            LanguageArg::De => Language::German,
+            LanguageArg::Es => Language::Spanish,
+            LanguageArg::Ja => Language::Japanese,
+        });
+    }
+    for path in paths {
+        stopwords
+            .add_reader(open(path)?)
+            .map_err(|e| Failure::count(path, e))?;
+    }
+    Ok(stopwords)
+}
+
+/// `--dict` と `--matrix` で指定されたファイルから形態素解析器を作る
+fn load_segmenter(
+    dicts: &[PathBuf],
+    matrix: &Option<PathBuf>,
+    pos: &[String],
+) -> Result<Segmenter, Failure> {
+    if dicts.is_empty() {
+        return Err(Failure::usage("morph requires at least one --dict FILE"));
+    }
+
+    let mut dict = Dictionary::new();
+    for path in dicts {
+        dict.add_csv(open(path)?)
+            .map_err(|e| Failure::count(path, e))?;
+    }
+    if let Some(path) = matrix {
+        dict.set_matrix(open(path)?)
+            .map_err(|e| Failure::count(path, e))?;
+    }
+
+    Ok(Segmenter::new(dict).keep_pos(pos))
+}
+
+/// 数える入力の一覧。--recursive ではディレクトリをたどり、たどれなかったものは skip に渡す
+fn inputs(cli: &InputArgs, skip: &mut impl FnMut(Failure)) -> Result<Vec<PathBuf>, Failure> {
+    if cli.files.is_empty() {
+        return Ok(vec![PathBuf::from("-")]);
+    }
+    if !cli.recursive {
+        return Ok(cli.files.clone());
+    }
+    let mut walk = Walk::new().hidden(cli.hidden).ignore_files(!cli.no_ignore);
+    for glob in &cli.include_glob {
+        walk = walk
+            .include(glob)
+            .map_err(|e| Failure::usage(e.to_string()))?;
+    }
+    for glob in &cli.exclude_glob {
+        walk = walk
+            .exclude(glob)
+            .map_err(|e| Failure::usage(e.to_string()))?;
+    }
+    let mut inputs = Vec::new();
+    for root in &cli.files {
+        if root == Path::new("-") {
+            inputs.push(root.clone());
+            continue;
+        }
+        for path in walk.files(root) {
+            match path {
+                Ok(path) => inputs.push(path),
+                Err(e) => skip(Failure::walk(e)),
+            }
+        }
+    }
+    Ok(inputs)
+}
+
+/// path を開いて read に渡す。path が `-` なら標準入力を渡す
+///
+/// 先頭にバイナリらしい内容があれば知らせて読み飛ばし、`false` を返す
+fn read_input(
+    path: &Path,
+    read: impl FnOnce(Box<dyn BufRead>) -> Result<(), CountError>,
+) -> Result<bool, Failure> {
+    let name = input_name(path);
+    let mut input: Box<dyn BufRead> = if path == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(open(path)?)
+    };
+    if skip_binary(path, &mut input)? {
+        return Ok(false);
+    }
+    read(input).map_err(|e| Failure::count(Path::new(&name), e))?;
+    Ok(true)
+}
+
+/// 先頭にバイナリらしい内容があれば知らせて `true` を返す
+fn skip_binary(path: &Path, input: &mut impl BufRead) -> Result<bool, Failure> {
+    let head = input.fill_buf().map_err(|e| Failure::io(path, e))?;
+    let binary = is_binary(head);
+    if binary {
+        report(format_args!("{}: skipping binary file", input_name(path)));
+    }
+    Ok(binary)
+}
+
+/// path に作ったファイルに write で書き出す
+fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> Result<(), Failure> {
+    let mut out = BufWriter::new(File::create(path).map_err(|e| Failure::io(path, e))?);
+    write(&mut out)
+        .and_then(|()| out.flush())
+        .map_err(|e| Failure::io(path, e))
+}
+
+/// メッセージや出力で使う入力の名前
+fn input_name(path: &Path) -> String {
+    if path == Path::new("-") {
+        "<stdin>".to_string()
+    } else {
+        path.display().to_string()
+    }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, Failure> {
+    if path.is_dir() {
+        return Err(Failure {
+            code: EX_NOINPUT,
+            message: format!("{}: is a directory (use --recursive)", path.display()),
+        });
+    }
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| Failure::open(path, e))
+}
+
+/// 別名の規則ごとの書き換え回数を標準エラー出力に書く
+fn report_rewrites(aliases: &Aliases, rewrites: &[usize]) {
+    for (rule, n) in aliases.rules().iter().zip(rewrites) {
+        let source = if rule.is_regex() {
+            format!("/{}/", rule.source())
+        } else {
+            rule.source().to_string()
+        };
+        report(format_args!(
+            "alias {} -> {}: {} rewrite(s)",
+            source,
+            rule.target(),
+            n
+        ));
+    }
+}
 
-    let freqs = count(reader, option);
-    println!("{:?}", freqs);
+/// 不正な UTF-8 を置き換えたり読み飛ばしたりした行があれば警告する
+fn warn_decode(name: &str, summary: DecodeSummary) {
+    if summary.replaced > 0 {
+        report(format_args!(
+            "warning: {}: replaced invalid UTF-8 in {} line(s)",
+            name, summary.replaced
+        ));
+    }
+    if summary.skipped > 0 {
+        report(format_args!(
+            "warning: {}: skipped {} line(s) with invalid UTF-8",
+            name, summary.skipped
+        ));
+    }
 }
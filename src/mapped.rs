@@ -0,0 +1,160 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::{try_count_borrowed, Config, CountError, DecodeSummary};
+
+/// メモリマップしたファイル
+///
+/// 一行ずつ `String` に読み込まずにファイルの中身を直接数え、
+/// キーはファイルの中身を借用したまま返す。
+///
+/// # Examples
+///
+/// ```no_run
+/// use fhiroki_bicycle_book_wordcount::{Config, CountOption, MappedFile};
+///
+/// // SAFETY: 数えている間に corpus.txt を書き換えるプロセスはない
+/// let file = unsafe { MappedFile::open("corpus.txt") }.unwrap();
+/// let (freq, _) = file.count(&Config::from(CountOption::Word)).unwrap();
+/// println!("{}", freq["the"]);
+/// ```
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    /// path をメモリマップする
+    ///
+    /// # Safety
+    /// 返した `MappedFile` と、そこから借用したキーが残っている間は、
+    /// このプロセスやほかのプロセスが path を書き換えたり切り詰めたりしてはならない。
+    /// 書き換えられると `&str` が UTF-8 として不正になるなどの未定義動作になり、
+    /// 切り詰められるとマップした範囲を読んだときに SIGBUS で異常終了する。
+    ///
+    /// # Errors
+    /// ファイルを開けなかった場合や、メモリマップできなかった場合
+    pub unsafe fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        MappedFile::map(&File::open(path)?)
+    }
+
+    /// 開いたファイルをメモリマップする
+    ///
+    /// # Safety
+    /// [`open`](#method.open) と同じ
+    pub(crate) unsafe fn map(file: &File) -> io::Result<Self> {
+        let map = Mmap::map(file)?;
+        Ok(MappedFile { map })
+    }
+
+    /// ファイルの中身
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// 設定に従って頻度を数える
+    ///
+    /// # Errors
+    /// [`try_count_borrowed`](fn.try_count_borrowed.html) と同じ
+    pub fn count(
+        &self,
+        config: &Config,
+    ) -> Result<(HashMap<Cow<'_, str>, usize>, DecodeSummary), CountError> {
+        try_count_borrowed(self.as_bytes(), config)
+    }
+}
+
+impl fmt::Debug for MappedFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MappedFile")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{try_count_with, Case, CountOption, Decode, Normalization, Stemmer};
+    use std::fs;
+    use std::num::NonZeroUsize;
+
+    fn owned(freqs: HashMap<Cow<'_, str>, usize>) -> HashMap<String, usize> {
+        freqs
+            .into_iter()
+            .map(|(k, n)| (k.into_owned(), n))
+            .collect()
+    }
+
+    #[test]
+    fn matches_buffered_count() {
+        let content = [
+            "Run runs\r\nrunning b\u{e9}b\u{e9}\n\nlast\n".as_bytes(),
+            b"b\xffd run\n",
+        ]
+        .concat();
+        let path = std::env::temp_dir().join(format!("wordcount-mapped-{}", std::process::id()));
+        fs::write(&path, &content).unwrap();
+        // SAFETY: このテストの中でしか使わない一時ファイル
+        let file = unsafe { MappedFile::open(&path) }.unwrap();
+
+        let configs = vec![
+            Config {
+                decode: Decode::Lossy,
+                ..Config::from(CountOption::Word)
+            },
+            Config {
+                decode: Decode::Skip,
+                normalize: Normalization {
+                    case: Case::Lower,
+                    ..Normalization::default()
+                },
+                stem: Some(Stemmer::English),
+                ..Config::from(CountOption::Word)
+            },
+            Config {
+                decode: Decode::Skip,
+                ..Config::from(CountOption::CharNgram(NonZeroUsize::new(2).unwrap()))
+            },
+        ];
+        for config in configs {
+            let (mapped, summary) = file.count(&config).unwrap();
+            let expected = try_count_with(&content[..], &config).unwrap();
+            assert_eq!(owned(mapped), expected.0, "{:?}", config.option);
+            assert_eq!(summary, expected.1);
+        }
+
+        let err = file.count(&Config::default()).unwrap_err();
+        let expected = try_count_with(&content[..], &Config::default()).unwrap_err();
+        assert_eq!(
+            (err.line(), err.offset()),
+            (expected.line(), expected.offset())
+        );
+
+        drop(file);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn borrows_keys_taken_verbatim() {
+        let config = Config {
+            normalize: Normalization {
+                case: Case::Lower,
+                ..Normalization::default()
+            },
+            ..Config::from(CountOption::Word)
+        };
+        let (freqs, _) = try_count_borrowed(b"abc ABC", &config).unwrap();
+        assert_eq!(freqs.len(), 1);
+        // 最初に現れた "abc" は正規化しても変わらないので借用する
+        assert!(matches!(freqs.keys().next(), Some(Cow::Borrowed("abc"))));
+
+        let (freqs, _) = try_count_borrowed(b"ABC abc", &config).unwrap();
+        assert!(matches!(freqs.keys().next(), Some(Cow::Owned(_))));
+    }
+}
@@ -0,0 +1,578 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::io::BufRead;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::decode::LineReader;
+use crate::{CountError, Decode, Token, Tokenizer};
+
+/// 未知語一つあたりの生起コスト
+const UNKNOWN_COST: i32 = 10000;
+
+/// 文脈IDは u16 なので、連接コスト表の各辺はこれより大きくならない
+const MAX_CONTEXT_IDS: i64 = u16::MAX as i64 + 1;
+
+/// 形態素解析に使う辞書
+///
+/// MeCab の IPADIC や UniDic と同じ形式の UTF-8 の CSV
+/// (`表層形,左文脈ID,右文脈ID,コスト,品詞,...`) と、
+/// 任意で連接コスト表 `matrix.def` を読み込む。
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    entries: HashMap<String, Vec<Entry>>,
+    max_len: usize,
+    pos: Vec<String>,
+    pos_ids: HashMap<String, usize>,
+    matrix: Option<Matrix>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    left_id: u16,
+    right_id: u16,
+    cost: i32,
+    pos: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Matrix {
+    right_size: usize,
+    costs: Vec<i32>,
+}
+
+impl Dictionary {
+    /// 空の辞書を作る
+    pub fn new() -> Self {
+        Dictionary::default()
+    }
+
+    /// CSV 形式の辞書を一つ読み込んで辞書を作る
+    ///
+    /// # Errors
+    /// [`add_csv`](#method.add_csv) と同じ
+    pub fn from_csv(input: impl BufRead) -> Result<Self, CountError> {
+        let mut dict = Dictionary::new();
+        dict.add_csv(input)?;
+        Ok(dict)
+    }
+
+    /// CSV 形式の辞書の見出し語を追加する
+    ///
+    /// 品詞は5列目から最大4列分を `,` でつないだもの (`名詞,固有名詞,地域,一般` など) とする。
+    ///
+    /// # Errors
+    /// 読み込みに失敗した場合や、列が足りない・数値が不正な行がある場合
+    pub fn add_csv(&mut self, input: impl BufRead) -> Result<(), CountError> {
+        let mut reader = LineReader::new(input, Decode::Strict);
+        loop {
+            let line = match reader.read_line()? {
+                Some(line) => line.into_owned(),
+                None => return Ok(()),
+            };
+            if line.is_empty() {
+                continue;
+            }
+            let fields = split_csv(&line);
+            if fields.len() < 4 || fields[0].is_empty() {
+                return Err(reader.syntax_error("expected `surface,left_id,right_id,cost,...`"));
+            }
+            let number = |i: usize, min: i64, max: i64| match fields[i].trim().parse::<i64>() {
+                Ok(n) if min <= n && n <= max => Ok(n),
+                _ => Err(reader.syntax_error(format!("invalid number `{}`", fields[i]))),
+            };
+            let left_id = number(1, 0, u16::MAX.into())? as u16;
+            let right_id = number(2, 0, u16::MAX.into())? as u16;
+            let cost = number(3, i32::MIN.into(), i32::MAX.into())? as i32;
+            let pos = fields[4..]
+                .iter()
+                .take(4)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(",");
+
+            let pos = self.intern_pos(pos);
+            let surface = fields[0].clone();
+            self.max_len = self.max_len.max(surface.len());
+            self.entries.entry(surface).or_default().push(Entry {
+                left_id,
+                right_id,
+                cost,
+                pos,
+            });
+        }
+    }
+
+    /// 連接コスト表 `matrix.def` を読み込む
+    ///
+    /// 1行目は `前件の文脈ID数 後件の文脈ID数`、2行目以降は
+    /// `前件の右文脈ID 後件の左文脈ID コスト`。読み込まない場合の連接コストはすべて 0。
+    ///
+    /// # Errors
+    /// 読み込みに失敗した場合や、数値が不正な行・範囲外の文脈IDがある場合、
+    /// 1行目の文脈ID数が 65536 を超える場合や表を確保できない場合
+    pub fn set_matrix(&mut self, input: impl BufRead) -> Result<(), CountError> {
+        let mut reader = LineReader::new(input, Decode::Strict);
+        let mut matrix: Option<Matrix> = None;
+        while let Some(line) = reader.read_line()? {
+            let numbers: Result<Vec<i64>, _> = line.split_whitespace().map(str::parse).collect();
+            let numbers = match numbers {
+                Ok(numbers) => numbers,
+                Err(e) => return Err(reader.syntax_error(e.to_string())),
+            };
+            match (&mut matrix, numbers.as_slice()) {
+                (_, []) => {}
+                (None, &[left, right]) if left >= 0 && right >= 0 => {
+                    if left > MAX_CONTEXT_IDS || right > MAX_CONTEXT_IDS {
+                        return Err(reader.syntax_error("matrix size out of range"));
+                    }
+                    let len = (left as usize)
+                        .checked_mul(right as usize)
+                        .ok_or_else(|| reader.syntax_error("matrix size out of range"))?;
+                    let mut costs = Vec::new();
+                    costs.try_reserve_exact(len).map_err(|e| {
+                        reader.syntax_error(format!("cannot allocate matrix: {}", e))
+                    })?;
+                    costs.resize(len, 0);
+                    matrix = Some(Matrix {
+                        right_size: right as usize,
+                        costs,
+                    });
+                }
+                (Some(m), &[left, right, cost]) if left >= 0 && right >= 0 => {
+                    let (left, right) = (left as usize, right as usize);
+                    // 掛け算があふれないように、行の数と比べてから位置を求める
+                    if right >= m.right_size || left >= m.costs.len() / m.right_size {
+                        return Err(reader.syntax_error("context id out of range"));
+                    }
+                    let cost = i32::try_from(cost)
+                        .map_err(|_| reader.syntax_error("cost out of range"))?;
+                    m.costs[left * m.right_size + right] = cost;
+                }
+                _ => return Err(reader.syntax_error("unexpected number of columns")),
+            }
+        }
+        self.matrix = matrix;
+        Ok(())
+    }
+
+    /// 登録されている見出し語の数
+    pub fn len(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    /// 見出し語が一つも登録されていなければ `true`
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn intern_pos(&mut self, pos: String) -> usize {
+        if let Some(&id) = self.pos_ids.get(&pos) {
+            return id;
+        }
+        let id = self.pos.len();
+        self.pos.push(pos.clone());
+        self.pos_ids.insert(pos, id);
+        id
+    }
+
+    fn connection(&self, right_id: u16, left_id: u16) -> i32 {
+        match &self.matrix {
+            Some(m) if (left_id as usize) < m.right_size => m
+                .costs
+                .get(right_id as usize * m.right_size + left_id as usize)
+                .copied()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+}
+
+/// `"` で囲まれたフィールドに対応した CSV の一行の分割
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// 辞書に基づいて日本語の文を形態素に分割するトークナイザ
+///
+/// 辞書の見出し語と未知語からなるラティスを作り、
+/// 生起コストと連接コストの和が最小になる分割をビタビアルゴリズムで求める。
+/// 空白は区切りとして扱い、トークンには含めない。
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use fhiroki_bicycle_book_wordcount::{count, CountOption, Dictionary, Segmenter};
+///
+/// let dict = Dictionary::from_csv(Cursor::new(
+///     "今日,0,0,100,名詞,副詞可能,*,*\n\
+///      は,0,0,100,助詞,係助詞,*,*\n\
+///      良い,0,0,100,形容詞,自立,*,*\n\
+///      天気,0,0,100,名詞,一般,*,*\n\
+///      です,0,0,100,助動詞,*,*,*\n",
+/// ))
+/// .unwrap();
+///
+/// let segmenter = Segmenter::new(dict);
+/// let freq = count(Cursor::new("今日は良い天気です"), CountOption::Morpheme(segmenter.clone()));
+/// assert_eq!(freq["天気"], 1);
+/// assert_eq!(freq["は"], 1);
+///
+/// let nouns = segmenter.keep_pos(&["名詞"]);
+/// let freq = count(Cursor::new("今日は良い天気です"), CountOption::Morpheme(nouns));
+/// assert_eq!(freq.len(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Segmenter {
+    dict: Arc<Dictionary>,
+    keep_pos: Vec<String>,
+}
+
+impl Segmenter {
+    /// dict を使って分割するトークナイザを作る
+    pub fn new(dict: Dictionary) -> Self {
+        Segmenter {
+            dict: Arc::new(dict),
+            keep_pos: Vec::new(),
+        }
+    }
+
+    /// 品詞が pos のいずれかで始まる形態素だけを数えるようにする
+    ///
+    /// `"名詞"` は `"名詞,一般,*,*"` にも `"名詞,固有名詞,地域,一般"` にもマッチする。
+    /// 空のときはすべての形態素を数える。
+    pub fn keep_pos<S: AsRef<str>>(mut self, pos: &[S]) -> Self {
+        self.keep_pos = pos.iter().map(|p| p.as_ref().to_string()).collect();
+        self
+    }
+
+    /// line を形態素に分割し、各形態素のバイト範囲と品詞を返す
+    pub fn segment<'a>(&'a self, line: &'a str) -> Vec<(Range<usize>, &'a str)> {
+        let mut morphemes = Vec::new();
+        let mut start = None;
+        for (i, c) in line.char_indices().chain(Some((line.len(), ' '))) {
+            match (start, c.is_whitespace()) {
+                (None, false) => start = Some(i),
+                (Some(s), true) => {
+                    self.viterbi(line, s, i, &mut morphemes);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        morphemes
+    }
+
+    fn viterbi<'a>(
+        &'a self,
+        line: &'a str,
+        begin: usize,
+        end: usize,
+        out: &mut Vec<(Range<usize>, &'a str)>,
+    ) {
+        let dict = &*self.dict;
+        let mut nodes: Vec<Node> = Vec::new();
+        // ends_at[i] は位置 begin + i で終わるノードの番号
+        let mut ends_at: Vec<Vec<usize>> = vec![Vec::new(); end - begin + 1];
+
+        for (i, _) in line[begin..end].char_indices() {
+            let pos = begin + i;
+            if pos != begin && ends_at[i].is_empty() {
+                continue;
+            }
+            let rest = &line[pos..end];
+            let mut found = false;
+            for (len, _) in rest
+                .char_indices()
+                .skip(1)
+                .chain(Some((rest.len(), ' ')))
+                .take_while(|&(len, _)| len <= dict.max_len)
+            {
+                if let Some(entries) = dict.entries.get(&rest[..len]) {
+                    found = true;
+                    for entry in entries {
+                        let span = pos..pos + len;
+                        self.push_node(&mut nodes, &mut ends_at, begin, span, *entry, None);
+                    }
+                }
+            }
+            for (len, pos_name) in unknown_words(rest, found) {
+                let entry = Entry {
+                    left_id: 0,
+                    right_id: 0,
+                    cost: UNKNOWN_COST,
+                    pos: usize::MAX,
+                };
+                let span = pos..pos + len;
+                self.push_node(&mut nodes, &mut ends_at, begin, span, entry, Some(pos_name));
+            }
+        }
+
+        let best = ends_at[end - begin].iter().min_by_key(|&&n| {
+            nodes[n].total + i64::from(dict.connection(nodes[n].entry.right_id, 0))
+        });
+        let mut path = Vec::new();
+        let mut current = best.copied();
+        while let Some(n) = current {
+            path.push(n);
+            current = nodes[n].prev;
+        }
+
+        for n in path.into_iter().rev() {
+            let node = &nodes[n];
+            let pos = match node.unknown_pos {
+                Some(pos) => pos,
+                None => dict.pos[node.entry.pos].as_str(),
+            };
+            let keep = |p: &String| {
+                pos.strip_prefix(p.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(','))
+            };
+            if self.keep_pos.is_empty() || self.keep_pos.iter().any(keep) {
+                out.push((node.start..node.end, pos));
+            }
+        }
+    }
+
+    fn push_node(
+        &self,
+        nodes: &mut Vec<Node>,
+        ends_at: &mut [Vec<usize>],
+        begin: usize,
+        span: Range<usize>,
+        entry: Entry,
+        unknown_pos: Option<&'static str>,
+    ) {
+        let dict = &*self.dict;
+        let Range { start, end } = span;
+        let prev = if start == begin {
+            Some((None, i64::from(dict.connection(0, entry.left_id))))
+        } else {
+            ends_at[start - begin]
+                .iter()
+                .map(|&p| {
+                    let connection = dict.connection(nodes[p].entry.right_id, entry.left_id);
+                    (Some(p), nodes[p].total + i64::from(connection))
+                })
+                .min_by_key(|&(_, cost)| cost)
+        };
+        if let Some((prev, cost)) = prev {
+            ends_at[end - begin].push(nodes.len());
+            nodes.push(Node {
+                start,
+                end,
+                entry,
+                total: cost + i64::from(entry.cost),
+                prev,
+                unknown_pos,
+            });
+        }
+    }
+}
+
+/// ラティス上のノード
+struct Node {
+    start: usize,
+    end: usize,
+    entry: Entry,
+    // i32 のコストを足し合わせても、一行の長さで桁あふれしないように i64 で持つ
+    total: i64,
+    prev: Option<usize>,
+    unknown_pos: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Kanji,
+    Hiragana,
+    Katakana,
+    Alpha,
+    Digit,
+    Other,
+}
+
+fn char_class(c: char) -> CharClass {
+    match c {
+        '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' | '々' | '〆' => CharClass::Kanji,
+        '\u{3041}'..='\u{309f}' => CharClass::Hiragana,
+        '\u{30a1}'..='\u{30ff}' | '\u{31f0}'..='\u{31ff}' | '\u{ff66}'..='\u{ff9f}' => {
+            CharClass::Katakana
+        }
+        c if c.is_numeric() => CharClass::Digit,
+        c if c.is_alphabetic() => CharClass::Alpha,
+        _ => CharClass::Other,
+    }
+}
+
+/// rest の先頭から始まる未知語の長さと品詞
+///
+/// カタカナ・英字・数字は同じ字種の並びを一語とする。
+/// それ以外は辞書に見出し語がない場合に限り一文字を一語とする。
+fn unknown_words(rest: &str, found: bool) -> Vec<(usize, &'static str)> {
+    let first = match rest.chars().next() {
+        Some(c) => c,
+        None => return Vec::new(),
+    };
+    let class = char_class(first);
+    let pos = match class {
+        CharClass::Digit => "名詞,数,*,*",
+        CharClass::Other => "記号,一般,*,*",
+        _ => "名詞,一般,*,*",
+    };
+
+    let mut words = Vec::new();
+    if let CharClass::Katakana | CharClass::Alpha | CharClass::Digit = class {
+        let len = rest
+            .char_indices()
+            .find(|&(_, c)| char_class(c) != class)
+            .map_or(rest.len(), |(i, _)| i);
+        words.push((len, pos));
+    }
+    if !found && words.iter().all(|&(len, _)| len != first.len_utf8()) {
+        words.push((first.len_utf8(), pos));
+    }
+    words
+}
+
+/// 同じ辞書を共有し、同じ品詞で絞り込んでいる場合に等しい
+impl PartialEq for Segmenter {
+    fn eq(&self, other: &Segmenter) -> bool {
+        Arc::ptr_eq(&self.dict, &other.dict) && self.keep_pos == other.keep_pos
+    }
+}
+
+impl Eq for Segmenter {}
+
+impl Hash for Segmenter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Arc::as_ptr(&self.dict) as usize).hash(state);
+        self.keep_pos.hash(state);
+    }
+}
+
+impl Tokenizer for Segmenter {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        Box::new(
+            self.segment(line)
+                .into_iter()
+                .map(move |(span, _)| Token::borrowed(line, span)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn words(segmenter: &Segmenter, line: &str) -> Vec<String> {
+        segmenter
+            .tokenize(line)
+            .map(|t| t.text.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn prefers_lower_cost_path() {
+        let dict = Dictionary::from_csv(Cursor::new(
+            "東京,0,0,3000,名詞,固有名詞,地域,一般\n\
+             東京都,0,0,2000,名詞,固有名詞,地域,一般\n\
+             都,0,0,3000,名詞,接尾,*,*\n\
+             に,0,0,100,助詞,格助詞,*,*\n\
+             住む,0,0,1000,動詞,自立,*,*\n",
+        ))
+        .unwrap();
+        let segmenter = Segmenter::new(dict);
+        assert_eq!(words(&segmenter, "東京都に住む"), ["東京都", "に", "住む"]);
+        assert_eq!(
+            words(&segmenter.keep_pos(&["名詞", "動詞"]), "東京都に住む"),
+            ["東京都", "住む"]
+        );
+    }
+
+    #[test]
+    fn connection_costs_change_segmentation() {
+        let csv = "くる,1,1,1000,動詞,自立,*,*\n\
+                   ま,2,2,1000,名詞,一般,*,*\n\
+                   くるま,3,3,2500,名詞,一般,*,*\n";
+        let mut dict = Dictionary::from_csv(Cursor::new(csv)).unwrap();
+        let segmenter = Segmenter::new(dict.clone());
+        assert_eq!(words(&segmenter, "くるま"), ["くる", "ま"]);
+
+        dict.set_matrix(Cursor::new("4 4\n1 2 1000\n")).unwrap();
+        let segmenter = Segmenter::new(dict);
+        assert_eq!(words(&segmenter, "くるま"), ["くるま"]);
+    }
+
+    #[test]
+    fn large_costs_do_not_overflow() {
+        let dict =
+            Dictionary::from_csv(Cursor::new(format!("語,0,0,{},名詞,一般,*,*\n", i32::MAX)))
+                .unwrap();
+        let segmenter = Segmenter::new(dict);
+        assert_eq!(words(&segmenter, "語語語").len(), 3);
+
+        let mut dict = Dictionary::from_csv(Cursor::new("語,0,0,1,名詞,一般,*,*\n")).unwrap();
+        let err = dict
+            .set_matrix(Cursor::new("1 1\n0 0 2147483648\n"))
+            .unwrap_err();
+        assert_eq!(err.line(), 2);
+    }
+
+    #[test]
+    fn rejects_matrix_larger_than_context_ids() {
+        let mut dict = Dictionary::new();
+        let err = dict
+            .set_matrix(Cursor::new("1000000 1000000\n"))
+            .unwrap_err();
+        assert!(
+            matches!(err, CountError::Syntax { line: 1, .. }),
+            "{:?}",
+            err
+        );
+        let err = dict
+            .set_matrix(Cursor::new("2 2\n9223372036854775807 0 1\n"))
+            .unwrap_err();
+        assert_eq!(err.line(), 2);
+    }
+
+    #[test]
+    fn unknown_words_are_grouped_by_char_class() {
+        let dict = Dictionary::from_csv(Cursor::new("の,0,0,100,助詞,連体化,*,*\n")).unwrap();
+        let segmenter = Segmenter::new(dict);
+        assert_eq!(
+            words(&segmenter, "Rust の カタカナ語 2020"),
+            ["Rust", "の", "カタカナ", "語", "2020"]
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_line() {
+        let err = Dictionary::from_csv(Cursor::new("a,0,0,1,名詞\nb,0,x,1,名詞\n")).unwrap_err();
+        match err {
+            CountError::Syntax { line, offset, .. } => assert_eq!((line, offset), (2, 15)),
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn split_csv_handles_quotes() {
+        assert_eq!(split_csv(r#""a,""b""",1,2"#), ["a,\"b\"", "1", "2"]);
+    }
+}
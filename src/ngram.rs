@@ -0,0 +1,241 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use crate::{Token, Tokenizer, WordTokenizer};
+
+/// n-gram を数えるときの窓の区切り方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct NgramOptions {
+    /// 空白や句読点の位置で窓をリセットし、それらを含む n-gram を数えない
+    pub reset_at_boundaries: bool,
+    /// 単語 n-gram で行をまたいだ n-gram も数える
+    pub cross_lines: bool,
+}
+
+/// 重なりを許して連続する n 文字ずつ切り出す
+///
+/// # Examples
+///
+/// ```
+/// use fhiroki_bicycle_book_wordcount::{CharNgramTokenizer, Tokenizer};
+///
+/// let bigrams: Vec<_> = CharNgramTokenizer::new(2)
+///     .tokenize("東京都")
+///     .map(|t| t.text.into_owned())
+///     .collect();
+/// assert_eq!(bigrams, ["東京", "京都"]);
+///
+/// let bigrams: Vec<_> = CharNgramTokenizer::new(2)
+///     .reset_at_boundaries(true)
+///     .tokenize("東京、京都")
+///     .map(|t| t.text.into_owned())
+///     .collect();
+/// assert_eq!(bigrams, ["東京", "京都"]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CharNgramTokenizer {
+    n: usize,
+    reset_at_boundaries: bool,
+}
+
+impl CharNgramTokenizer {
+    /// n 文字ずつ切り出すトークナイザを作る
+    ///
+    /// # Panics
+    /// n が 0 の場合
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "n-gram size must be positive");
+        CharNgramTokenizer {
+            n,
+            reset_at_boundaries: false,
+        }
+    }
+
+    /// `true` にすると空白や句読点の位置で窓をリセットする
+    pub fn reset_at_boundaries(mut self, reset: bool) -> Self {
+        self.reset_at_boundaries = reset;
+        self
+    }
+}
+
+impl Tokenizer for CharNgramTokenizer {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        let mut spans = Vec::new();
+        // window は窓の中の各文字の開始位置
+        let mut window = Vec::with_capacity(self.n);
+        for (i, c) in line.char_indices() {
+            if self.reset_at_boundaries && is_boundary(c) {
+                window.clear();
+                continue;
+            }
+            if window.len() == self.n {
+                window.remove(0);
+            }
+            window.push(i);
+            if window.len() == self.n {
+                spans.push(window[0]..i + c.len_utf8());
+            }
+        }
+        Box::new(
+            spans
+                .into_iter()
+                .map(move |span| Token::borrowed(line, span)),
+        )
+    }
+}
+
+/// [`WordTokenizer`](struct.WordTokenizer.html) で切り出した単語を
+/// 連続する n 個ずつ空白でつないで切り出す
+///
+/// 行をまたいだ n-gram は数えない。行をまたぐ場合は
+/// [`NgramOptions::cross_lines`](struct.NgramOptions.html#structfield.cross_lines)
+/// を指定した [`Counter`](struct.Counter.html) を使う。
+///
+/// # Examples
+///
+/// ```
+/// use fhiroki_bicycle_book_wordcount::{Tokenizer, WordNgramTokenizer};
+///
+/// let trigrams: Vec<_> = WordNgramTokenizer::new(3)
+///     .tokenize("in order to, in order")
+///     .map(|t| (t.text.into_owned(), t.span))
+///     .collect();
+/// assert_eq!(trigrams[0], ("in order to".to_string(), 0..11));
+/// assert_eq!(trigrams.len(), 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct WordNgramTokenizer {
+    n: usize,
+    words: WordTokenizer,
+}
+
+impl WordNgramTokenizer {
+    /// n 単語ずつ切り出すトークナイザを作る
+    ///
+    /// # Panics
+    /// n が 0 の場合
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "n-gram size must be positive");
+        WordNgramTokenizer {
+            n,
+            words: WordTokenizer::new(),
+        }
+    }
+}
+
+impl Tokenizer for WordNgramTokenizer {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        let words: Vec<_> = self.words.tokenize(line).collect();
+        let grams: Vec<_> = words
+            .windows(self.n)
+            .map(|w| Token {
+                text: Cow::Owned(join(w.iter().map(|t| &*t.text))),
+                span: w[0].span.start..w[self.n - 1].span.end,
+            })
+            .collect();
+        Box::new(grams.into_iter())
+    }
+}
+
+/// 行をまたいで直近 n 個のトークンを保持する窓
+#[derive(Debug, Clone)]
+pub(crate) struct Window {
+    n: usize,
+    tokens: VecDeque<String>,
+}
+
+impl Window {
+    pub(crate) fn new(n: usize) -> Self {
+        assert!(n > 0, "n-gram size must be positive");
+        Window {
+            n,
+            tokens: VecDeque::with_capacity(n),
+        }
+    }
+
+    /// token を窓に入れ、窓が埋まっていればその n-gram を返す
+    pub(crate) fn push(&mut self, token: &str) -> Option<String> {
+        if self.tokens.len() == self.n {
+            self.tokens.pop_front();
+        }
+        self.tokens.push_back(token.to_string());
+        if self.tokens.len() == self.n {
+            Some(join(self.tokens.iter().map(String::as_str)))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.tokens.clear();
+    }
+}
+
+fn join<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    let mut joined = String::new();
+    for (i, word) in words.enumerate() {
+        if i > 0 {
+            joined.push(' ');
+        }
+        joined.push_str(word);
+    }
+    joined
+}
+
+/// n-gram の窓をリセットする空白や句読点か
+pub(crate) fn is_boundary(c: char) -> bool {
+    c.is_whitespace()
+        || c.is_ascii_punctuation()
+        || match c {
+            // 一般句読点、CJKの記号と句読点、全角の記号
+            '\u{2000}'..='\u{206f}' | '\u{3000}'..='\u{303f}' | '\u{30fb}' => true,
+            '\u{ff01}'..='\u{ff0f}' | '\u{ff1a}'..='\u{ff20}' => true,
+            '\u{ff3b}'..='\u{ff40}' | '\u{ff5b}'..='\u{ff65}' => true,
+            _ => false,
+        }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn grams(tokenizer: CharNgramTokenizer, line: &str) -> Vec<String> {
+        tokenizer
+            .tokenize(line)
+            .map(|t| t.text.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn windows_overlap() {
+        assert_eq!(grams(CharNgramTokenizer::new(3), "abcd"), ["abc", "bcd"]);
+        assert!(grams(CharNgramTokenizer::new(3), "ab").is_empty());
+    }
+
+    #[test]
+    fn word_ngrams_stay_within_line() {
+        let grams: Vec<_> = WordNgramTokenizer::new(2)
+            .tokenize("a b c")
+            .map(|t| t.text.into_owned())
+            .collect();
+        assert_eq!(grams, ["a b", "b c"]);
+        assert_eq!(WordNgramTokenizer::new(4).tokenize("a b c").count(), 0);
+    }
+
+    #[test]
+    fn window_slides() {
+        let mut window = Window::new(2);
+        assert_eq!(window.push("a"), None);
+        assert_eq!(window.push("b").as_deref(), Some("a b"));
+        assert_eq!(window.push("c").as_deref(), Some("b c"));
+        window.clear();
+        assert_eq!(window.push("d"), None);
+    }
+
+    #[test]
+    fn windows_include_boundaries_unless_reset() {
+        assert_eq!(grams(CharNgramTokenizer::new(2), "a b"), ["a ", " b"]);
+        let reset = CharNgramTokenizer::new(2).reset_at_boundaries(true);
+        assert_eq!(grams(reset, "ab cd。「ef」"), ["ab", "cd", "ef"]);
+    }
+}
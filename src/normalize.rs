@@ -0,0 +1,199 @@
+use std::borrow::Cow;
+use unicode_normalization::char::is_combining_mark;
+
+use crate::JapaneseNormalization;
+use unicode_normalization::{is_nfc_quick, is_nfkc_quick, IsNormalized, UnicodeNormalization};
+
+/// 大文字・小文字の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Case {
+    /// そのまま
+    Preserve,
+    /// `str::to_lowercase` で小文字にする
+    Lower,
+    /// Unicode の完全なケースフォールディング (ß → ss など)
+    Fold,
+}
+
+/// デフォルトは [`Preserve`](enum.Case.html#variant.Preserve)
+impl Default for Case {
+    fn default() -> Self {
+        Case::Preserve
+    }
+}
+
+/// Unicode 正規化形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Form {
+    /// 正規化しない
+    Preserve,
+    /// 正規化形式 C (NFC)
+    Nfc,
+    /// 正規化形式 KC (NFKC)
+    Nfkc,
+}
+
+/// デフォルトは [`Preserve`](enum.Form.html#variant.Preserve)
+impl Default for Form {
+    fn default() -> Self {
+        Form::Preserve
+    }
+}
+
+/// 頻度を数える前にキーに適用する正規化
+///
+/// 日本語の表記ゆれの正規化、ダイアクリティカルマークの除去、大文字・小文字の統一、
+/// Unicode 正規化の順に適用する。
+///
+/// # Examples
+///
+/// ```
+/// use fhiroki_bicycle_book_wordcount::{Case, Form, Normalization};
+///
+/// let normalization = Normalization {
+///     case: Case::Fold,
+///     form: Form::Nfkc,
+///     strip_diacritics: true,
+///     ..Normalization::default()
+/// };
+/// assert_eq!(normalization.apply("Straße"), "strasse");
+/// assert_eq!(normalization.apply("Cafe\u{301}"), "cafe");
+/// assert_eq!(normalization.apply("ＡＢＣ"), "abc");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Normalization {
+    /// 大文字・小文字の扱い
+    pub case: Case,
+    /// Unicode 正規化形式
+    pub form: Form,
+    /// `true` のときはアクセント記号などの結合文字を取り除く。かなの濁点・半濁点は残す
+    pub strip_diacritics: bool,
+    /// 日本語の表記ゆれの正規化
+    pub japanese: JapaneseNormalization,
+}
+
+impl Normalization {
+    /// 何も変更しない正規化なら `true`
+    pub fn is_identity(&self) -> bool {
+        *self == Normalization::default()
+    }
+
+    /// text を正規化する。変更がなければ借用したまま返す
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        if self.is_identity() {
+            return text;
+        }
+
+        text = apply_cow(text, |text| self.japanese.apply(text));
+
+        if self.strip_diacritics && text.nfd().any(is_diacritic) {
+            text = Cow::Owned(text.nfd().filter(|&c| !is_diacritic(c)).nfc().collect());
+        }
+
+        match self.case {
+            Case::Preserve => {}
+            Case::Lower => {
+                if text.chars().any(|c| c.to_lowercase().ne(Some(c))) {
+                    text = Cow::Owned(text.to_lowercase());
+                }
+            }
+            Case::Fold => {
+                let folded = caseless::default_case_fold_str(&text);
+                if folded != *text {
+                    text = Cow::Owned(folded);
+                }
+            }
+        }
+
+        match self.form {
+            Form::Preserve => {}
+            Form::Nfc => {
+                if is_nfc_quick(text.chars()) != IsNormalized::Yes {
+                    let normalized: String = text.nfc().collect();
+                    if normalized != *text {
+                        text = Cow::Owned(normalized);
+                    }
+                }
+            }
+            Form::Nfkc => {
+                if is_nfkc_quick(text.chars()) != IsNormalized::Yes {
+                    let normalized: String = text.nfkc().collect();
+                    if normalized != *text {
+                        text = Cow::Owned(normalized);
+                    }
+                }
+            }
+        }
+
+        text
+    }
+
+    /// [`apply`](#method.apply) と同じだが、所有している文字列はそのまま使い回す
+    pub(crate) fn apply_cow<'a>(&self, text: Cow<'a, str>) -> Cow<'a, str> {
+        apply_cow(text, |text| self.apply(text))
+    }
+}
+
+/// text に apply を適用する。変更がなければ、所有している文字列もそのまま返す
+fn apply_cow<'a>(text: Cow<'a, str>, apply: impl FnOnce(&str) -> Cow<'_, str>) -> Cow<'a, str> {
+    let changed = match apply(&text) {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(normalized) => Some(normalized),
+    };
+    changed.map_or(text, Cow::Owned)
+}
+
+/// 取り除く結合文字か。かなの濁点・半濁点は除く
+fn is_diacritic(c: char) -> bool {
+    is_combining_mark(c) && c != '\u{3099}' && c != '\u{309a}'
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_borrows_text() {
+        assert!(matches!(
+            Normalization::default().apply("The"),
+            Cow::Borrowed("The")
+        ));
+    }
+
+    #[test]
+    fn lower_and_fold_differ_on_sharp_s() {
+        let lower = Normalization {
+            case: Case::Lower,
+            ..Normalization::default()
+        };
+        let fold = Normalization {
+            case: Case::Fold,
+            ..Normalization::default()
+        };
+        assert_eq!(lower.apply("THE Straße"), "the straße");
+        assert_eq!(fold.apply("THE Straße"), "the strasse");
+        assert!(matches!(lower.apply("the"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn nfc_merges_decomposed_forms() {
+        let nfc = Normalization {
+            form: Form::Nfc,
+            ..Normalization::default()
+        };
+        assert_eq!(nfc.apply("cafe\u{301}"), "caf\u{e9}");
+        assert_eq!(nfc.apply("caf\u{e9}"), "caf\u{e9}");
+    }
+
+    #[test]
+    fn strip_diacritics_keeps_hangul() {
+        let strip = Normalization {
+            strip_diacritics: true,
+            ..Normalization::default()
+        };
+        assert_eq!(strip.apply("naïve résumé"), "naive resume");
+        assert_eq!(strip.apply("한국어"), "한국어");
+        assert_eq!(strip.apply("ガイド"), "ガイド");
+    }
+}
@@ -0,0 +1,475 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::{Config, CountError, CountOption, Counter, MappedFile};
+
+/// ファイルを分割する大きさのデフォルト (16 MiB)
+const DEFAULT_CHUNK_SIZE: u64 = 16 << 20;
+
+/// 複数のスレッドでファイルの頻度を数えるカウンタ
+///
+/// ファイルをスレッドに振り分け、大きなファイルは改行の直後で区切った断片に分けて数え、
+/// 最後にファイルごとに足し合わせる。トークンは一行ずつ切り出すので、結果は
+/// [`Counter::feed_reader`](struct.Counter.html#method.feed_reader) で順に数えたものと一致する。
+/// ただし [`NgramOptions::cross_lines`](struct.NgramOptions.html#structfield.cross_lines)
+/// を指定した単語 n-gram は行をまたぐので、ファイルを分割せずに数える。
+/// パイプなど通常のファイルでないものも、開き直せないので分割せずに数える。
+///
+/// # Examples
+///
+/// ```no_run
+/// use fhiroki_bicycle_book_wordcount::{Config, CountOption, ParallelCounter};
+///
+/// let counter = ParallelCounter::new(Config::from(CountOption::Word)).jobs(4);
+/// let total = counter.count_file("corpus.txt").unwrap();
+/// println!("{}", total.get("the"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParallelCounter {
+    config: Config,
+    jobs: usize,
+    chunk_size: u64,
+    mmap: bool,
+}
+
+impl ParallelCounter {
+    /// 設定を指定して、使えるスレッドの数だけ並列に数えるカウンタを作る
+    pub fn new(config: Config) -> Self {
+        ParallelCounter {
+            config,
+            jobs: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            mmap: false,
+        }
+    }
+
+    /// 同時に使うスレッドの数。0 にすると使える CPU の数にする
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// ファイルを分割する大きさの目安 (バイト)。1 行はこれより長くても分割しない
+    ///
+    /// # Panics
+    /// chunk_size が 0 の場合
+    pub fn chunk_size(mut self, chunk_size: u64) -> Self {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// `true` にするとファイルを [`MappedFile`](struct.MappedFile.html) でメモリマップし、
+    /// 一行ずつバッファに読み込まずに数える
+    ///
+    /// メモリマップはファイルごとに一度だけ行い、断片で共有する。
+    /// パイプなど通常のファイルでないものは、メモリマップせずに読み込む。
+    ///
+    /// # Safety
+    /// 数えている間に、数えるファイルを書き換えたり切り詰めたりしてはならない
+    /// ([`MappedFile::open`](struct.MappedFile.html#method.open) を参照)。
+    pub unsafe fn mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    /// path の頻度を数える
+    ///
+    /// # Errors
+    /// [`count_files`](#method.count_files) と同じ
+    pub fn count_file(&self, path: impl AsRef<Path>) -> Result<Counter, CountError> {
+        self.count_files(&[path]).pop().unwrap()
+    }
+
+    /// paths のそれぞれの頻度を数え、paths と同じ順に返す
+    ///
+    /// # Errors
+    /// ファイルを開けなかった場合は 1 行目の
+    /// [`CountError::Io`](enum.CountError.html#variant.Io) を返す。
+    /// そのほかは [`Counter::feed_reader`](struct.Counter.html#method.feed_reader) と同じで、
+    /// エラーの行番号と位置は順に数えた場合と一致する。
+    pub fn count_files<P: AsRef<Path>>(&self, paths: &[P]) -> Vec<Result<Counter, CountError>> {
+        let jobs = match self.jobs {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            jobs => jobs,
+        };
+        let mut files = Vec::new();
+        let mut sources = Vec::new();
+        let mut chunks = Vec::new();
+        for (file, path) in paths.iter().enumerate() {
+            match self.split(path.as_ref(), jobs) {
+                Ok((source, ranges)) => {
+                    let starts = ranges.iter().map(|&(start, _)| start).collect();
+                    files.push(Merged::new(
+                        Counter::with_config(self.config.clone()),
+                        starts,
+                    ));
+                    let ranges = ranges.into_iter().enumerate();
+                    chunks.extend(ranges.map(|(i, range)| (file, i, sources.len(), range)));
+                    sources.push(source);
+                }
+                Err(e) => files.push(Merged::failed(open_error(e))),
+            }
+        }
+
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..jobs.min(chunks.len()) {
+                let sender = sender.clone();
+                let (next, chunks, sources) = (&next, &chunks, &sources);
+                scope.spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let (file, chunk, source, range) = match chunks.get(i) {
+                        Some(&chunk) => chunk,
+                        None => break,
+                    };
+                    let result = self.count_range(&sources[source], range);
+                    if sender.send((file, chunk, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+            for (file, chunk, result) in receiver {
+                files[file].push(chunk, result);
+            }
+        });
+
+        files.into_iter().map(Merged::finish).collect()
+    }
+
+    /// path を開き、改行の直後で区切った [開始, 終了) のバイト範囲に分ける
+    fn split<'a>(&self, path: &'a Path, jobs: usize) -> io::Result<(Source<'a>, Vec<(u64, u64)>)> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        // パイプなどはメモリマップも開き直すこともできないので、開いたものを分割せずに読む
+        if !metadata.is_file() {
+            return Ok((Source::Stream(file), vec![(0, u64::MAX)]));
+        }
+        let len = metadata.len();
+        let cross_lines = match self.config.option {
+            CountOption::WordNgram(_) => self.config.ngram.cross_lines,
+            _ => false,
+        };
+        let whole = jobs == 1 || cross_lines || len <= self.chunk_size;
+
+        if self.mmap {
+            // SAFETY: mmap(true) の呼び出し側が、数えている間にファイルが変わらないことを保証する
+            let mapped = unsafe { MappedFile::map(&file) }?;
+            let bytes = mapped.as_bytes();
+            let ranges = if whole {
+                vec![(0, u64::MAX)]
+            } else {
+                self.ranges(bytes.len() as u64, |probe| {
+                    let rest = &bytes[probe as usize..];
+                    let n = rest
+                        .iter()
+                        .position(|&b| b == b'\n')
+                        .map_or(rest.len(), |i| i + 1);
+                    Ok(probe + n as u64)
+                })?
+            };
+            return Ok((Source::Mapped(mapped), ranges));
+        }
+        if whole {
+            return Ok((Source::File(path), vec![(0, u64::MAX)]));
+        }
+        let mut line = Vec::new();
+        let ranges = self.ranges(len, |probe| {
+            file.seek(SeekFrom::Start(probe))?;
+            line.clear();
+            let n = BufReader::new(&mut file).read_until(b'\n', &mut line)?;
+            Ok(probe + n as u64)
+        })?;
+        Ok((Source::File(path), ranges))
+    }
+
+    /// 長さ len の中身を chunk_size ごとに区切る
+    ///
+    /// line_end は、渡した位置を含む行の終わり (改行の直後か len) を返す。
+    fn ranges(
+        &self,
+        len: u64,
+        mut line_end: impl FnMut(u64) -> io::Result<u64>,
+    ) -> io::Result<Vec<(u64, u64)>> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        while start + self.chunk_size < len {
+            // 区切りたい位置の直前の行の終わりまで読み進める
+            let end = line_end(start + self.chunk_size - 1)?;
+            if end >= len {
+                break;
+            }
+            ranges.push((start, end));
+            start = end;
+        }
+        ranges.push((start, u64::MAX));
+        Ok(ranges)
+    }
+
+    /// source の [開始, 終了) のバイト範囲を数える
+    fn count_range(
+        &self,
+        source: &Source,
+        (start, end): (u64, u64),
+    ) -> Result<Counter, CountError> {
+        let mut counter = Counter::with_config(self.config.clone());
+        match source {
+            Source::File(path) => {
+                let mut file = File::open(path).map_err(open_error)?;
+                if start > 0 {
+                    file.seek(SeekFrom::Start(start)).map_err(open_error)?;
+                }
+                counter.feed_reader(BufReader::new(file.take(end - start)))?;
+            }
+            Source::Stream(file) => counter.feed_reader(BufReader::new(file))?,
+            Source::Mapped(file) => {
+                let bytes = file.as_bytes();
+                let len = bytes.len() as u64;
+                counter.feed_bytes(&bytes[start.min(len) as usize..end.min(len) as usize])?;
+            }
+        }
+        Ok(counter)
+    }
+}
+
+/// 数えるファイルの読み方
+enum Source<'a> {
+    /// 通常のファイル。断片ごとに開き直して読む
+    File(&'a Path),
+    /// パイプなど開き直せないもの。開いたものを先頭から読む
+    Stream(File),
+    /// メモリマップした通常のファイル。すべての断片で共有する
+    Mapped(MappedFile),
+}
+
+/// ひとつのファイルの断片の結果を、先頭から順に足し合わせる
+struct Merged {
+    result: Result<Counter, CountError>,
+    // 断片の開始位置
+    starts: Vec<u64>,
+    // 次に足し合わせる断片と、それより前の断片の行数の合計
+    next: usize,
+    lines: usize,
+    // 先に届いた後ろの断片
+    pending: BTreeMap<usize, Result<Counter, CountError>>,
+}
+
+impl Merged {
+    fn new(counter: Counter, starts: Vec<u64>) -> Self {
+        Merged {
+            result: Ok(counter),
+            starts,
+            next: 0,
+            lines: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    fn failed(e: CountError) -> Self {
+        Merged {
+            result: Err(e),
+            starts: Vec::new(),
+            next: 0,
+            lines: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    fn push(&mut self, chunk: usize, result: Result<Counter, CountError>) {
+        self.pending.insert(chunk, result);
+        while let Some(result) = self.pending.remove(&self.next) {
+            let counter = match &mut self.result {
+                Ok(counter) => counter,
+                Err(_) => return,
+            };
+            match result {
+                Ok(other) => {
+                    self.lines += other.summary().lines;
+                    counter.merge(other);
+                }
+                // 断片の中での位置を、それより前の断片の行数と開始位置でずらす
+                Err(e) => self.result = Err(e.shift(self.lines, self.starts[self.next] as usize)),
+            }
+            self.next += 1;
+        }
+    }
+
+    fn finish(self) -> Result<Counter, CountError> {
+        self.result
+    }
+}
+
+fn open_error(source: io::Error) -> CountError {
+    CountError::Io {
+        line: 1,
+        offset: 0,
+        source,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{try_count_with, Decode, Keywords, NgramOptions, Pattern};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use std::path::PathBuf;
+
+    const TEXT: &str = "The quick brown fox\r\njumps over the lazy dog.\n\n\
+                        東京都に住む。すもももももももものうち\n\
+                        don't stop 3.14 e\u{301}te\u{301}\n\
+                        a very long line that is longer than the chunk size itself, really\n\
+                        fox dog fox\nlast line without newline";
+
+    fn write(name: &str, content: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "wordcount-parallel-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn sequential(content: &[u8], config: &Config) -> HashMap<String, usize> {
+        try_count_with(content, config).unwrap().0
+    }
+
+    #[test]
+    fn matches_sequential_count_for_every_option() {
+        let path = write("options", TEXT.as_bytes());
+        let options = vec![
+            CountOption::Char,
+            CountOption::CharNgram(NonZeroUsize::new(3).unwrap()),
+            CountOption::Grapheme,
+            CountOption::Word,
+            CountOption::WordNgram(NonZeroUsize::new(2).unwrap()),
+            CountOption::UnicodeWord,
+            CountOption::Line,
+            CountOption::Pattern(Pattern::new("[a-z]+").unwrap()),
+            CountOption::Keywords(Keywords::new(&["fox", "the", "もも"]).unwrap()),
+        ];
+        for option in options {
+            for &cross_lines in &[false, true] {
+                let config = Config {
+                    option: option.clone(),
+                    ngram: NgramOptions {
+                        cross_lines,
+                        ..NgramOptions::default()
+                    },
+                    ..Config::default()
+                };
+                for &chunk_size in &[1, 7, 64, 1 << 20] {
+                    for &mmap in &[false, true] {
+                        let counter = ParallelCounter::new(config.clone())
+                            .jobs(3)
+                            .chunk_size(chunk_size);
+                        // SAFETY: このテストの中でしか使わない一時ファイル
+                        let counter = unsafe { counter.mmap(mmap) };
+                        let parallel = counter.count_file(&path).unwrap();
+                        assert_eq!(
+                            parallel.summary().lines,
+                            TEXT.lines().count(),
+                            "{:?}",
+                            option
+                        );
+                        assert_eq!(
+                            parallel.into_surface_map(),
+                            sequential(TEXT.as_bytes(), &config),
+                            "{:?} chunk_size={} mmap={}",
+                            option,
+                            chunk_size,
+                            mmap
+                        );
+                    }
+                }
+            }
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reports_the_same_error_as_sequential_count() {
+        let content = b"aa\nbb\ncc\nd\xffd\nee\n";
+        let path = write("error", content);
+        let config = Config::default();
+        let expected = try_count_with(&content[..], &config).unwrap_err();
+        for &mmap in &[false, true] {
+            let counter = ParallelCounter::new(config.clone()).jobs(2).chunk_size(3);
+            // SAFETY: このテストの中でしか使わない一時ファイル
+            let counter = unsafe { counter.mmap(mmap) };
+            let parallel = counter.count_file(&path).unwrap_err();
+            assert_eq!(
+                (parallel.line(), parallel.offset()),
+                (expected.line(), expected.offset()),
+                "mmap={}",
+                mmap
+            );
+        }
+
+        let config = Config {
+            decode: Decode::Skip,
+            ..Config::default()
+        };
+        let counter = ParallelCounter::new(config.clone()).jobs(2).chunk_size(3);
+        let parallel = counter.count_file(&path).unwrap();
+        assert_eq!(parallel.summary().skipped, 1);
+        assert_eq!(parallel.into_map(), sequential(content, &config));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_pipes_without_reopening() {
+        let path =
+            std::env::temp_dir().join(format!("wordcount-parallel-fifo-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let status = std::process::Command::new("mkfifo")
+            .arg(&path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let config = Config::default();
+        for &mmap in &[false, true] {
+            let writer = {
+                let path = path.clone();
+                std::thread::spawn(move || fs::write(path, TEXT).unwrap())
+            };
+            let counter = ParallelCounter::new(config.clone()).jobs(3).chunk_size(7);
+            // SAFETY: パイプはメモリマップしない
+            let counter = unsafe { counter.mmap(mmap) };
+            let parallel = counter.count_file(&path).unwrap();
+            writer.join().unwrap();
+            assert_eq!(
+                parallel.into_map(),
+                sequential(TEXT.as_bytes(), &config),
+                "mmap={}",
+                mmap
+            );
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn keeps_results_in_input_order() {
+        let a = write("a", b"x y\n");
+        let b = write("b", b"y z z\n");
+        let missing = a.with_extension("missing");
+        let results = ParallelCounter::new(Config::default())
+            .jobs(4)
+            .count_files(&[&a, &missing, &b]);
+        assert_eq!(results[0].as_ref().unwrap().get("x"), 1);
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().get("z"), 2);
+        fs::remove_file(a).unwrap();
+        fs::remove_file(b).unwrap();
+    }
+}
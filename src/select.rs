@@ -0,0 +1,208 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use regex::Regex;
+
+/// 並べ替えの基準
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SortKey {
+    /// 頻度
+    Count,
+    /// キーの辞書順
+    Key,
+    /// キーの文字数
+    Length,
+}
+
+/// デフォルトは [`Count`](enum.SortKey.html#variant.Count)
+impl Default for SortKey {
+    fn default() -> Self {
+        SortKey::Count
+    }
+}
+
+impl SortKey {
+    /// 向きを指定しない場合の向き。頻度は多い順、キーと文字数は昇順
+    pub fn default_order(self) -> Order {
+        match self {
+            SortKey::Count => Order::Descending,
+            SortKey::Key | SortKey::Length => Order::Ascending,
+        }
+    }
+}
+
+/// 並べ替えの向き
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Order {
+    /// 昇順
+    Ascending,
+    /// 降順
+    Descending,
+}
+
+/// デフォルトは [`SortKey`](enum.SortKey.html) のデフォルトに合わせた
+/// [`Descending`](enum.Order.html#variant.Descending)。
+/// ほかの基準では [`SortKey::default_order`](enum.SortKey.html#method.default_order) を使う
+impl Default for Order {
+    fn default() -> Self {
+        Order::Descending
+    }
+}
+
+/// 頻度の一覧を絞り込んで並べ替える条件
+///
+/// 頻度とキーで絞り込んだ後に並べ替え、先頭から最大 `top` 件を残す。
+/// 基準が同じ場合は向きによらずキーの昇順に並べるので、結果は常に同じ順番になる。
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use regex::Regex;
+/// use fhiroki_bicycle_book_wordcount::{Order, Select, SortKey};
+///
+/// let freqs: HashMap<String, usize> = vec![("aa", 3), ("b", 1), ("cc", 3), ("_x", 5)]
+///     .into_iter()
+///     .map(|(k, n)| (k.to_string(), n))
+///     .collect();
+///
+/// let select = Select {
+///     exclude: Some(Regex::new("^_").unwrap()),
+///     top: Some(2),
+///     ..Select::default()
+/// };
+/// assert_eq!(select.apply(&freqs), [("aa", 3), ("cc", 3)]);
+///
+/// let select = Select {
+///     sort: SortKey::Length,
+///     order: Order::Ascending,
+///     min_count: Some(2),
+///     ..Select::default()
+/// };
+/// assert_eq!(select.apply(&freqs), [("_x", 5), ("aa", 3), ("cc", 3)]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Select {
+    /// 並べ替えの基準
+    pub sort: SortKey,
+    /// 並べ替えの向き
+    pub order: Order,
+    /// 残す件数の上限
+    pub top: Option<usize>,
+    /// 頻度がこれより小さいキーは残さない
+    pub min_count: Option<usize>,
+    /// 頻度がこれより大きいキーは残さない
+    pub max_count: Option<usize>,
+    /// キーがこの正規表現にマッチするものだけを残す
+    pub include: Option<Regex>,
+    /// キーがこの正規表現にマッチするものは残さない
+    pub exclude: Option<Regex>,
+}
+
+impl Select {
+    /// freqs を絞り込んで並べ替える
+    pub fn apply<'a>(&self, freqs: &'a HashMap<String, usize>) -> Vec<(&'a str, usize)> {
+        let mut entries: Vec<_> = freqs
+            .iter()
+            .map(|(key, &n)| (key.as_str(), n))
+            .filter(|&(key, n)| self.keeps(key, n))
+            .collect();
+        entries.sort_by(|a, b| {
+            let ordering = match self.sort {
+                SortKey::Count => a.1.cmp(&b.1),
+                SortKey::Key => Ordering::Equal,
+                SortKey::Length => a.0.chars().count().cmp(&b.0.chars().count()),
+            };
+            let ordering = match self.order {
+                Order::Ascending => ordering,
+                Order::Descending => ordering.reverse(),
+            };
+            let by_key = match (self.sort, self.order) {
+                (SortKey::Key, Order::Descending) => b.0.cmp(a.0),
+                _ => a.0.cmp(b.0),
+            };
+            ordering.then(by_key)
+        });
+        if let Some(top) = self.top {
+            entries.truncate(top);
+        }
+        entries
+    }
+
+    fn keeps(&self, key: &str, n: usize) -> bool {
+        self.min_count.is_none_or(|min| n >= min)
+            && self.max_count.is_none_or(|max| n <= max)
+            && self.include.as_ref().is_none_or(|re| re.is_match(key))
+            && !self.exclude.as_ref().is_some_and(|re| re.is_match(key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn freqs() -> HashMap<String, usize> {
+        vec![("b", 2), ("a", 2), ("ccc", 1), ("dd", 4)]
+            .into_iter()
+            .map(|(k, n)| (k.to_string(), n))
+            .collect()
+    }
+
+    #[test]
+    fn ties_are_broken_by_key_in_either_order() {
+        let freqs = freqs();
+        let descending = Select::default();
+        assert_eq!(
+            descending.apply(&freqs),
+            [("dd", 4), ("a", 2), ("b", 2), ("ccc", 1)]
+        );
+        let ascending = Select {
+            order: Order::Ascending,
+            ..Select::default()
+        };
+        assert_eq!(
+            ascending.apply(&freqs),
+            [("ccc", 1), ("a", 2), ("b", 2), ("dd", 4)]
+        );
+    }
+
+    #[test]
+    fn key_order_can_be_reversed() {
+        let freqs = freqs();
+        let select = Select {
+            sort: SortKey::Key,
+            ..Select::default()
+        };
+        let keys: Vec<_> = select.apply(&freqs).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, ["dd", "ccc", "b", "a"]);
+    }
+
+    #[test]
+    fn default_order_depends_on_sort_key() {
+        let freqs = freqs();
+        let keys = |sort: SortKey| {
+            let select = Select {
+                sort,
+                order: sort.default_order(),
+                ..Select::default()
+            };
+            let entries = select.apply(&freqs);
+            entries.into_iter().map(|(k, _)| k).collect::<Vec<_>>()
+        };
+        assert_eq!(keys(SortKey::Count), ["dd", "a", "b", "ccc"]);
+        assert_eq!(keys(SortKey::Key), ["a", "b", "ccc", "dd"]);
+        assert_eq!(keys(SortKey::Length), ["a", "b", "dd", "ccc"]);
+    }
+
+    #[test]
+    fn filters_apply_before_top() {
+        let freqs = freqs();
+        let select = Select {
+            max_count: Some(2),
+            include: Some(Regex::new("^[a-c]").unwrap()),
+            top: Some(2),
+            ..Select::default()
+        };
+        assert_eq!(select.apply(&freqs), [("a", 2), ("b", 2)]);
+    }
+}
@@ -0,0 +1,45 @@
+use std::borrow::Cow;
+
+use rust_stemmers::Algorithm;
+
+/// 単語を語幹にそろえる Snowball ステマー
+///
+/// [`Config::stem`](struct.Config.html#structfield.stem) に指定すると、
+/// [`CountOption::Word`](enum.CountOption.html#variant.Word) と
+/// [`CountOption::UnicodeWord`](enum.CountOption.html#variant.UnicodeWord)
+/// で数える単語を語幹にそろえる。
+/// ステマーは小文字の単語を前提にしているので、
+/// [`Case::Lower`](enum.Case.html#variant.Lower) などと組み合わせる。
+///
+/// # Examples
+///
+/// ```
+/// use fhiroki_bicycle_book_wordcount::Stemmer;
+///
+/// assert_eq!(Stemmer::English.stem("running"), "run");
+/// assert_eq!(Stemmer::English.stem("runs"), "run");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stemmer {
+    /// 英語 (Porter2)
+    English,
+    /// フランス語
+    French,
+    /// ドイツ語
+    German,
+    /// スペイン語
+    Spanish,
+}
+
+impl Stemmer {
+    /// word の語幹。変更がなければ借用したまま返す
+    pub fn stem<'a>(self, word: &'a str) -> Cow<'a, str> {
+        let algorithm = match self {
+            Stemmer::English => Algorithm::English,
+            Stemmer::French => Algorithm::French,
+            Stemmer::German => Algorithm::German,
+            Stemmer::Spanish => Algorithm::Spanish,
+        };
+        rust_stemmers::Stemmer::create(algorithm).stem(word)
+    }
+}
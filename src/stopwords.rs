@@ -0,0 +1,450 @@
+use std::collections::BTreeSet;
+use std::io::BufRead;
+use std::sync::Arc;
+
+use crate::decode::LineReader;
+use crate::{CountError, Decode, Normalization};
+
+/// 組み込みのストップワードの言語
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    /// 英語
+    English,
+    /// フランス語
+    French,
+    /// ドイツ語
+    German,
+    /// スペイン語
+    Spanish,
+    /// 日本語 (助詞・助動詞などのひらがなの語)
+    Japanese,
+}
+
+impl Language {
+    /// 組み込みの言語の一覧
+    pub const ALL: [Language; 5] = [
+        Language::English,
+        Language::French,
+        Language::German,
+        Language::Spanish,
+        Language::Japanese,
+    ];
+
+    /// `en` や `ja` などの ISO 639-1 の言語コード
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::French => "fr",
+            Language::German => "de",
+            Language::Spanish => "es",
+            Language::Japanese => "ja",
+        }
+    }
+
+    /// 言語コードから言語を探す
+    pub fn from_code(code: &str) -> Option<Language> {
+        Language::ALL.iter().copied().find(|l| l.code() == code)
+    }
+
+    fn words(self) -> &'static [&'static str] {
+        match self {
+            Language::English => ENGLISH,
+            Language::French => FRENCH,
+            Language::German => GERMAN,
+            Language::Spanish => SPANISH,
+            Language::Japanese => JAPANESE,
+        }
+    }
+}
+
+/// 頻度を数えないストップワードの集合
+///
+/// [`Config::stopwords`](struct.Config.html#structfield.stopwords) に指定すると、
+/// [`Normalization`](struct.Normalization.html) を適用した後のキーがこの集合に含まれる場合は数えない。
+/// 集合の語にも同じ正規化を適用してから比べるので、`été` は `strip_diacritics` を指定した
+/// キーの `ete` とも一致する。組み込みの一覧はすべて小文字なので、大文字で始まる語も
+/// 取り除くには [`Case::Lower`](enum.Case.html#variant.Lower) などと組み合わせる。
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use fhiroki_bicycle_book_wordcount::{try_count_with, Case, Config, Language, StopWords};
+///
+/// let mut stopwords = StopWords::builtin(Language::English);
+/// stopwords.add_reader(Cursor::new("# 一行に一語\nfox\n")).unwrap();
+///
+/// let mut config = Config::default();
+/// config.normalize.case = Case::Lower;
+/// config.stopwords = stopwords;
+///
+/// let (freqs, _) = try_count_with(Cursor::new("The fox and THE dog"), &config).unwrap();
+/// assert_eq!(freqs.len(), 1);
+/// assert_eq!(freqs["dog"], 1);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct StopWords {
+    words: Arc<BTreeSet<String>>,
+}
+
+impl StopWords {
+    /// 空の集合を作る
+    pub fn new() -> Self {
+        StopWords::default()
+    }
+
+    /// language の組み込みの一覧を持つ集合を作る
+    pub fn builtin(language: Language) -> Self {
+        let mut stopwords = StopWords::new();
+        stopwords.add_builtin(language);
+        stopwords
+    }
+
+    /// 一行に一語ずつ書かれた一覧を読み込んで集合を作る
+    ///
+    /// # Errors
+    /// [`add_reader`](#method.add_reader) と同じ
+    pub fn from_reader(input: impl BufRead) -> Result<Self, CountError> {
+        let mut stopwords = StopWords::new();
+        stopwords.add_reader(input)?;
+        Ok(stopwords)
+    }
+
+    /// language の組み込みの一覧を追加する
+    pub fn add_builtin(&mut self, language: Language) {
+        for word in language.words() {
+            self.insert(*word);
+        }
+    }
+
+    /// 一行に一語ずつ書かれた一覧を追加する
+    ///
+    /// 前後の空白は取り除き、空行と `#` で始まる行は読み飛ばす。
+    ///
+    /// # Errors
+    /// 読み込みに失敗した場合や、UTF-8 として不正な行がある場合
+    pub fn add_reader(&mut self, input: impl BufRead) -> Result<(), CountError> {
+        let mut reader = LineReader::new(input, Decode::Strict);
+        while let Some(line) = reader.read_line()? {
+            let word = line.trim();
+            if !word.is_empty() && !word.starts_with('#') {
+                self.insert(word);
+            }
+        }
+        Ok(())
+    }
+
+    /// word を追加する
+    pub fn insert(&mut self, word: impl Into<String>) {
+        Arc::make_mut(&mut self.words).insert(word.into());
+    }
+
+    /// すべての語に normalization を適用した集合
+    pub(crate) fn normalized(&self, normalization: &Normalization) -> StopWords {
+        if normalization.is_identity() {
+            return self.clone();
+        }
+        let words = self
+            .words
+            .iter()
+            .map(|word| normalization.apply(word).into_owned())
+            .collect();
+        StopWords {
+            words: Arc::new(words),
+        }
+    }
+
+    /// word がストップワードなら `true`
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word)
+    }
+
+    /// ストップワードの数
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// ストップワードがひとつもなければ `true`
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+const ENGLISH: &[&str] = &[
+    "a",
+    "about",
+    "above",
+    "after",
+    "again",
+    "against",
+    "all",
+    "am",
+    "an",
+    "and",
+    "any",
+    "are",
+    "as",
+    "at",
+    "be",
+    "because",
+    "been",
+    "before",
+    "being",
+    "below",
+    "between",
+    "both",
+    "but",
+    "by",
+    "can",
+    "could",
+    "did",
+    "do",
+    "does",
+    "doing",
+    "down",
+    "during",
+    "each",
+    "few",
+    "for",
+    "from",
+    "further",
+    "had",
+    "has",
+    "have",
+    "having",
+    "he",
+    "her",
+    "here",
+    "hers",
+    "herself",
+    "him",
+    "himself",
+    "his",
+    "how",
+    "i",
+    "if",
+    "in",
+    "into",
+    "is",
+    "it",
+    "its",
+    "itself",
+    "just",
+    "me",
+    "more",
+    "most",
+    "my",
+    "myself",
+    "no",
+    "nor",
+    "not",
+    "now",
+    "of",
+    "off",
+    "on",
+    "once",
+    "only",
+    "or",
+    "other",
+    "our",
+    "ours",
+    "ourselves",
+    "out",
+    "over",
+    "own",
+    "same",
+    "she",
+    "should",
+    "so",
+    "some",
+    "such",
+    "than",
+    "that",
+    "the",
+    "their",
+    "theirs",
+    "them",
+    "themselves",
+    "then",
+    "there",
+    "these",
+    "they",
+    "this",
+    "those",
+    "through",
+    "to",
+    "too",
+    "under",
+    "until",
+    "up",
+    "very",
+    "was",
+    "we",
+    "were",
+    "what",
+    "when",
+    "where",
+    "which",
+    "while",
+    "who",
+    "whom",
+    "why",
+    "will",
+    "with",
+    "would",
+    "you",
+    "your",
+    "yours",
+    "yourself",
+    "yourselves",
+];
+
+const FRENCH: &[&str] = &[
+    "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "et", "eux", "il",
+    "ils", "je", "la", "le", "les", "leur", "lui", "ma", "mais", "me", "même", "mes", "moi", "mon",
+    "ne", "nos", "notre", "nous", "on", "ou", "par", "pas", "pour", "qu", "que", "qui", "sa", "se",
+    "ses", "son", "sur", "ta", "te", "tes", "toi", "ton", "tu", "un", "une", "vos", "votre",
+    "vous", "c", "d", "j", "l", "à", "m", "n", "s", "t", "y", "été", "est", "sont", "était",
+    "être", "avoir", "a", "ont", "cette", "cet",
+];
+
+const GERMAN: &[&str] = &[
+    "aber", "alle", "als", "also", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "bist",
+    "da", "damit", "dann", "das", "dass", "dein", "dem", "den", "der", "des", "dich", "die", "dir",
+    "doch", "du", "durch", "ein", "eine", "einem", "einen", "einer", "eines", "er", "es", "euch",
+    "euer", "für", "hat", "hatte", "ich", "ihr", "ihre", "im", "in", "ist", "ja", "kein", "man",
+    "mich", "mir", "mit", "nach", "nicht", "noch", "nur", "ob", "oder", "sein", "sich", "sie",
+    "sind", "so", "über", "um", "und", "uns", "unser", "vom", "von", "vor", "war", "waren", "was",
+    "weil", "wenn", "wie", "wir", "wird", "zu", "zum", "zur",
+];
+
+const SPANISH: &[&str] = &[
+    "a", "al", "algo", "como", "con", "de", "del", "el", "ella", "ellas", "ellos", "en", "entre",
+    "era", "es", "esa", "ese", "eso", "esta", "este", "esto", "fue", "ha", "hay", "la", "las",
+    "le", "les", "lo", "los", "me", "mi", "muy", "más", "nada", "ni", "no", "nos", "o", "para",
+    "pero", "por", "porque", "que", "qué", "se", "ser", "si", "sin", "sobre", "su", "sus",
+    "también", "te", "tu", "un", "una", "uno", "unos", "y", "ya", "yo", "él",
+];
+
+const JAPANESE: &[&str] = &[
+    "あそこ",
+    "あっ",
+    "あの",
+    "ある",
+    "い",
+    "いる",
+    "う",
+    "うち",
+    "え",
+    "お",
+    "か",
+    "が",
+    "かつ",
+    "から",
+    "こう",
+    "ここ",
+    "こと",
+    "この",
+    "これ",
+    "さ",
+    "さらに",
+    "し",
+    "しかし",
+    "する",
+    "そう",
+    "そこ",
+    "その",
+    "それ",
+    "た",
+    "だ",
+    "たち",
+    "ため",
+    "で",
+    "でき",
+    "です",
+    "では",
+    "と",
+    "という",
+    "として",
+    "な",
+    "など",
+    "なっ",
+    "なる",
+    "に",
+    "において",
+    "の",
+    "ので",
+    "は",
+    "へ",
+    "ます",
+    "また",
+    "まで",
+    "も",
+    "もの",
+    "や",
+    "よう",
+    "より",
+    "られ",
+    "れ",
+    "れる",
+    "を",
+    "ん",
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Case, Config, CountOption, Counter, JapaneseNormalization, Kana};
+    use std::io::Cursor;
+
+    #[test]
+    fn builtin_lists_are_lowercase() {
+        for &language in &Language::ALL {
+            for word in language.words() {
+                assert_eq!(*word, word.to_lowercase(), "{:?}", language);
+            }
+            assert_eq!(Language::from_code(language.code()), Some(language));
+        }
+    }
+
+    #[test]
+    fn list_is_normalized_like_keys() {
+        let mut stopwords = StopWords::builtin(Language::French);
+        stopwords.add_builtin(Language::German);
+        let mut counter = Counter::with_config(Config {
+            normalize: Normalization {
+                case: Case::Lower,
+                strip_diacritics: true,
+                ..Normalization::default()
+            },
+            stopwords,
+            ..Config::default()
+        });
+        counter.feed("Été ete über uber fur Tal");
+        assert_eq!(counter.iter().collect::<Vec<_>>(), [("tal", 1)]);
+
+        let mut counter = Counter::with_config(Config {
+            option: CountOption::Char,
+            normalize: Normalization {
+                japanese: JapaneseNormalization {
+                    kana: Kana::Katakana,
+                    ..JapaneseNormalization::default()
+                },
+                ..Normalization::default()
+            },
+            stopwords: StopWords::builtin(Language::Japanese),
+            ..Config::default()
+        });
+        counter.feed("猫のノ");
+        assert_eq!(counter.iter().collect::<Vec<_>>(), [("猫", 1)]);
+    }
+
+    #[test]
+    fn reader_skips_comments_and_blank_lines() {
+        let stopwords =
+            StopWords::from_reader(Cursor::new("# comment\n\n  foo \r\nbar\n")).unwrap();
+        assert_eq!(stopwords.len(), 2);
+        assert!(stopwords.contains("foo"));
+        assert!(!stopwords.contains("# comment"));
+    }
+}
@@ -0,0 +1,460 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// 一行から切り出したトークン
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Token<'a> {
+    /// 頻度を数えるキーになる文字列
+    pub text: Cow<'a, str>,
+    /// 行の先頭からのバイト範囲
+    pub span: Range<usize>,
+}
+
+impl<'a> Token<'a> {
+    /// line の span の範囲を借用するトークンを作る
+    pub fn borrowed(line: &'a str, span: Range<usize>) -> Self {
+        Token {
+            text: Cow::Borrowed(&line[span.clone()]),
+            span,
+        }
+    }
+}
+
+/// 一行の文字列をトークンに分割する
+///
+/// [`count_with`](fn.count_with.html) や
+/// [`Counter::with_tokenizer`](struct.Counter.html#method.with_tokenizer) に渡すことで、
+/// [`CountOption`](enum.CountOption.html) にない規則で頻度を数えられる。
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use fhiroki_bicycle_book_wordcount::{count_with, Token, Tokenizer};
+///
+/// /// カンマで区切られたフィールド
+/// struct Fields;
+///
+/// impl Tokenizer for Fields {
+///     fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+///         let mut start = 0;
+///         Box::new(line.split(',').map(move |field| {
+///             let span = start..start + field.len();
+///             start = span.end + 1;
+///             Token::borrowed(line, span)
+///         }))
+///     }
+/// }
+///
+/// let freq = count_with(Cursor::new("a,b\nb,c d"), &Fields);
+/// assert_eq!(freq["b"], 2);
+/// assert_eq!(freq["c d"], 1);
+/// ```
+pub trait Tokenizer {
+    /// line をトークンに分割する。line は末尾の改行を含まない
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a>;
+}
+
+impl<T: Tokenizer + ?Sized> Tokenizer for &T {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        (**self).tokenize(line)
+    }
+}
+
+impl<T: Tokenizer + ?Sized> Tokenizer for Box<T> {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        (**self).tokenize(line)
+    }
+}
+
+impl<T: Tokenizer + ?Sized> Tokenizer for Arc<T> {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        (**self).tokenize(line)
+    }
+}
+
+/// Unicodeの１文字ずつに分割する
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CharTokenizer;
+
+impl Tokenizer for CharTokenizer {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        Box::new(
+            line.char_indices()
+                .map(move |(i, c)| Token::borrowed(line, i..i + c.len_utf8())),
+        )
+    }
+}
+
+/// 拡張書記素クラスタ (UAX #29) ごとに分割する
+///
+/// 結合文字や絵文字の ZWJ シーケンス、国旗なども見た目どおり１文字として扱う。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct GraphemeTokenizer;
+
+impl Tokenizer for GraphemeTokenizer {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        Box::new(
+            line.grapheme_indices(true)
+                .map(move |(i, g)| Token::borrowed(line, i..i + g.len())),
+        )
+    }
+}
+
+/// 正規表現の \w+ にマッチする単語に分割する
+#[derive(Debug, Clone)]
+pub struct WordTokenizer {
+    re: Regex,
+}
+
+impl WordTokenizer {
+    /// 単語に分割するトークナイザを作る
+    pub fn new() -> Self {
+        WordTokenizer {
+            re: Regex::new(r"\w+").unwrap(),
+        }
+    }
+}
+
+impl Default for WordTokenizer {
+    fn default() -> Self {
+        WordTokenizer::new()
+    }
+}
+
+impl Tokenizer for WordTokenizer {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        Box::new(
+            self.re
+                .find_iter(line)
+                .map(move |m| Token::borrowed(line, m.start()..m.end())),
+        )
+    }
+}
+
+/// ユーザが指定した正規表現にマッチする部分に分割する
+///
+/// [`with_template`](#method.with_template) でテンプレートを指定すると、
+/// マッチ全体の代わりにキャプチャグループから組み立てた文字列をキーにする。
+/// 空文字列になったキーは数えない。
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use fhiroki_bicycle_book_wordcount::{count, CountOption, Pattern};
+///
+/// assert!(Pattern::new(r"[A-Za-z']+").is_ok());
+/// assert!(Pattern::new(r"[A-Z").is_err());
+///
+/// let pattern = Pattern::with_template(r"(\w+) status=(?P<status>\d{3})", "${status}").unwrap();
+/// let freq = count(Cursor::new("GET status=200\nPOST status=404\nGET status=200"), CountOption::Pattern(pattern));
+/// assert_eq!(freq["200"], 2);
+/// assert_eq!(freq["404"], 1);
+///
+/// let pattern = Pattern::with_template(r"(\w+) status=(?P<status>\d{3})", "$1 ${status}").unwrap();
+/// let freq = count(Cursor::new("GET status=200\nPOST status=404\nGET status=200"), CountOption::Pattern(pattern));
+/// assert_eq!(freq["GET 200"], 2);
+/// ```
+#[derive(Clone)]
+pub struct Pattern {
+    re: Regex,
+    template: Option<String>,
+    key: Key,
+}
+
+/// マッチからキーを作る方法
+#[derive(Debug, Clone)]
+enum Key {
+    /// マッチ全体
+    Match,
+    /// 一つのキャプチャグループ
+    Group(usize),
+    /// `Captures::expand` で展開するテンプレート
+    Template(String),
+}
+
+impl Pattern {
+    /// pattern をコンパイルする
+    ///
+    /// # Errors
+    /// pattern が正規表現として不正な場合
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(|re| Pattern {
+            re,
+            template: None,
+            key: Key::Match,
+        })
+    }
+
+    /// pattern をコンパイルし、マッチ全体の代わりに template を展開した文字列をキーにする
+    ///
+    /// template の書式は `Captures::expand` と同じで、`$1` や `${name}` で
+    /// キャプチャグループを参照する。`$$` は `$` そのもの。
+    ///
+    /// # Errors
+    /// pattern が正規表現として不正な場合や、template が存在しないグループを参照している場合
+    pub fn with_template(pattern: &str, template: &str) -> Result<Self, regex::Error> {
+        let re = Regex::new(pattern)?;
+        let refs = group_refs(template);
+        for group in &refs {
+            if group_index(&re, group).is_none() {
+                return Err(regex::Error::Syntax(format!(
+                    "unknown capture group `{}` in template `{}`",
+                    group, template
+                )));
+            }
+        }
+
+        let whole = |group: &str| {
+            template == format!("${}", group) || template == format!("${{{}}}", group)
+        };
+        let key = match refs.as_slice() {
+            [group] if whole(group) => Key::Group(group_index(&re, group).unwrap()),
+            _ => Key::Template(template.to_string()),
+        };
+        Ok(Pattern {
+            re,
+            template: Some(template.to_string()),
+            key,
+        })
+    }
+
+    /// 元の正規表現の文字列
+    pub fn as_str(&self) -> &str {
+        self.re.as_str()
+    }
+
+    /// キーを組み立てるテンプレート
+    pub fn template(&self) -> Option<&str> {
+        self.template.as_deref()
+    }
+}
+
+/// template が参照しているグループの名前または番号を返す
+fn group_refs(template: &str) -> Vec<&str> {
+    let mut refs = Vec::new();
+    let mut rest = template;
+    while let Some(i) = rest.find('$') {
+        rest = &rest[i + 1..];
+        if let Some(stripped) = rest.strip_prefix('$') {
+            rest = stripped;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            if let Some(end) = braced.find('}') {
+                refs.push(&braced[..end]);
+                rest = &braced[end + 1..];
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c == '_' || c.is_ascii_alphanumeric()))
+                .unwrap_or(rest.len());
+            if end > 0 {
+                refs.push(&rest[..end]);
+            }
+            rest = &rest[end..];
+        }
+    }
+    refs
+}
+
+/// 名前または番号で指定されたグループの番号
+fn group_index(re: &Regex, group: &str) -> Option<usize> {
+    match group.parse::<usize>() {
+        Ok(i) if i < re.captures_len() => Some(i),
+        Ok(_) => None,
+        Err(_) => re.capture_names().position(|name| name == Some(group)),
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = regex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pattern::new(s)
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pattern")
+            .field("regex", &self.as_str())
+            .field("template", &self.template)
+            .finish()
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.as_str() == other.as_str() && self.template == other.template
+    }
+}
+
+impl Eq for Pattern {}
+
+impl Hash for Pattern {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+        self.template.hash(state);
+    }
+}
+
+impl Tokenizer for Pattern {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        match &self.key {
+            Key::Match => Box::new(
+                self.re
+                    .find_iter(line)
+                    .filter(|m| m.start() < m.end())
+                    .map(move |m| Token::borrowed(line, m.start()..m.end())),
+            ),
+            Key::Group(i) => Box::new(
+                self.re
+                    .captures_iter(line)
+                    .filter_map(move |caps| caps.get(*i))
+                    .filter(|m| m.start() < m.end())
+                    .map(move |m| Token::borrowed(line, m.start()..m.end())),
+            ),
+            Key::Template(template) => {
+                Box::new(self.re.captures_iter(line).filter_map(move |caps| {
+                    let mut text = String::new();
+                    caps.expand(template, &mut text);
+                    let m = caps.get(0).unwrap();
+                    if text.is_empty() {
+                        None
+                    } else {
+                        Some(Token {
+                            text: Cow::Owned(text),
+                            span: m.start()..m.end(),
+                        })
+                    }
+                }))
+            }
+        }
+    }
+}
+
+/// Unicode の単語境界 (UAX #29) で単語に分割する
+///
+/// `\w+` と違い、"don't" のような短縮形や "3.14" のような数値を一語として扱う。
+/// 空白や句読点だけの区間は単語に含めない。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct UnicodeWordTokenizer;
+
+impl Tokenizer for UnicodeWordTokenizer {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        Box::new(
+            line.unicode_word_indices()
+                .map(move |(i, w)| Token::borrowed(line, i..i + w.len())),
+        )
+    }
+}
+
+/// 一行全体を一つのトークンにする
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct LineTokenizer;
+
+impl Tokenizer for LineTokenizer {
+    fn tokenize<'a>(&'a self, line: &'a str) -> Box<dyn Iterator<Item = Token<'a>> + 'a> {
+        Box::new(std::iter::once(Token::borrowed(line, 0..line.len())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spans(tokenizer: &impl Tokenizer, line: &str) -> Vec<(String, Range<usize>)> {
+        tokenizer
+            .tokenize(line)
+            .map(|t| (t.text.into_owned(), t.span))
+            .collect()
+    }
+
+    #[test]
+    fn char_tokenizer_spans_are_byte_offsets() {
+        assert_eq!(
+            spans(&CharTokenizer, "aあb"),
+            [
+                ("a".to_string(), 0..1),
+                ("あ".to_string(), 1..4),
+                ("b".to_string(), 4..5)
+            ]
+        );
+    }
+
+    #[test]
+    fn grapheme_tokenizer_keeps_clusters() {
+        let line = "e\u{301}👨\u{200d}👩\u{200d}👧🇯🇵";
+        let tokens: Vec<_> = GraphemeTokenizer
+            .tokenize(line)
+            .map(|t| t.text.into_owned())
+            .collect();
+        assert_eq!(tokens, ["e\u{301}", "👨\u{200d}👩\u{200d}👧", "🇯🇵"]);
+    }
+
+    #[test]
+    fn word_tokenizer_spans() {
+        assert_eq!(
+            spans(&WordTokenizer::new(), "aa, bb"),
+            [("aa".to_string(), 0..2), ("bb".to_string(), 4..6)]
+        );
+    }
+
+    #[test]
+    fn unicode_word_tokenizer_keeps_contractions_and_numbers() {
+        assert_eq!(
+            spans(&UnicodeWordTokenizer, "don't pay 3.14, ok?"),
+            [
+                ("don't".to_string(), 0..5),
+                ("pay".to_string(), 6..9),
+                ("3.14".to_string(), 10..14),
+                ("ok".to_string(), 16..18)
+            ]
+        );
+    }
+
+    #[test]
+    fn pattern_skips_empty_matches() {
+        let pattern = Pattern::new(r"[0-9a-f]*").unwrap();
+        assert_eq!(
+            spans(&pattern, "id=ff0a;"),
+            [("d".to_string(), 1..2), ("ff0a".to_string(), 3..7)]
+        );
+    }
+
+    #[test]
+    fn pattern_with_group_borrows_group_span() {
+        let pattern = Pattern::with_template(r"status=(\d{3})", "$1").unwrap();
+        assert_eq!(
+            spans(&pattern, "status=200 status=abc status=404"),
+            [("200".to_string(), 7..10), ("404".to_string(), 29..32)]
+        );
+    }
+
+    #[test]
+    fn pattern_skips_unmatched_optional_group() {
+        let pattern = Pattern::with_template(r"a(?P<b>b)?", "${b}").unwrap();
+        assert_eq!(
+            spans(&pattern, "ab a ab"),
+            [("b".to_string(), 1..2), ("b".to_string(), 6..7)]
+        );
+    }
+
+    #[test]
+    fn pattern_template_rejects_unknown_group() {
+        assert!(Pattern::with_template(r"(\d+)", "$2").is_err());
+        assert!(Pattern::with_template(r"(?P<n>\d+)", "${m}").is_err());
+        assert!(Pattern::with_template(r"(?P<n>\d+)", "$$ ${n}").is_ok());
+    }
+
+    #[test]
+    fn line_tokenizer_yields_whole_line() {
+        assert_eq!(spans(&LineTokenizer, ""), [(String::new(), 0..0)]);
+    }
+}
@@ -0,0 +1,241 @@
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+
+/// バイナリかどうかを判定するために調べる先頭のバイト数
+pub const BINARY_CHECK_LEN: usize = 8000;
+
+/// 先頭の [`BINARY_CHECK_LEN`](constant.BINARY_CHECK_LEN.html) バイトまでに
+/// NUL を含んでいればバイナリとみなす
+///
+/// # Examples
+///
+/// ```
+/// use fhiroki_bicycle_book_wordcount::is_binary;
+///
+/// assert!(is_binary(b"\x7fELF\x02\x01\x01\x00"));
+/// assert!(!is_binary("テキスト\n".as_bytes()));
+/// ```
+pub fn is_binary(head: &[u8]) -> bool {
+    head[..head.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+/// ディレクトリをたどって数える対象のファイルを集める条件
+///
+/// デフォルトでは `.gitignore`、`.ignore` に書かれたファイルと、
+/// 名前が `.` で始まるファイルやディレクトリを読み飛ばす。
+/// glob はたどり始めたディレクトリからの相対パスとファイル名のどちらかにマッチすればよい。
+///
+/// # Examples
+///
+/// ```no_run
+/// use fhiroki_bicycle_book_wordcount::Walk;
+///
+/// let walk = Walk::new()
+///     .include("*.md")
+///     .unwrap()
+///     .exclude("vendor")
+///     .unwrap();
+/// for path in walk.files("docs") {
+///     println!("{}", path.unwrap().display());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Walk {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    // include, exclude をまとめたもの
+    include_set: GlobSet,
+    exclude_set: GlobSet,
+    hidden: bool,
+    ignore_files: bool,
+}
+
+impl Default for Walk {
+    fn default() -> Self {
+        Walk {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            include_set: GlobSet::empty(),
+            exclude_set: GlobSet::empty(),
+            hidden: false,
+            ignore_files: true,
+        }
+    }
+}
+
+impl Walk {
+    /// デフォルトの条件を作る
+    pub fn new() -> Self {
+        Walk::default()
+    }
+
+    /// glob にマッチするファイルだけを集める。複数指定した場合はどれかにマッチすればよい
+    ///
+    /// # Errors
+    /// glob が不正な場合や、まとめた glob が大きすぎる場合
+    pub fn include(mut self, glob: &str) -> Result<Self, globset::Error> {
+        self.include.push(Glob::new(glob)?);
+        self.include_set = glob_set(&self.include)?;
+        Ok(self)
+    }
+
+    /// glob にマッチするファイルやディレクトリは読み飛ばす
+    ///
+    /// # Errors
+    /// glob が不正な場合や、まとめた glob が大きすぎる場合
+    pub fn exclude(mut self, glob: &str) -> Result<Self, globset::Error> {
+        self.exclude.push(Glob::new(glob)?);
+        self.exclude_set = glob_set(&self.exclude)?;
+        Ok(self)
+    }
+
+    /// `true` にすると名前が `.` で始まるファイルやディレクトリもたどる
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// `false` にすると `.gitignore` や `.ignore` を無視する
+    pub fn ignore_files(mut self, ignore_files: bool) -> Self {
+        self.ignore_files = ignore_files;
+        self
+    }
+
+    /// root 以下のファイルをパスの順に返す
+    ///
+    /// root がファイルの場合は、glob によらずそのファイルだけを返す。
+    /// シンボリックリンクはたどらない。
+    pub fn files(
+        &self,
+        root: impl AsRef<Path>,
+    ) -> impl Iterator<Item = Result<PathBuf, ignore::Error>> {
+        let root = root.as_ref().to_path_buf();
+        let include = self.include_set.clone();
+        let exclude = self.exclude_set.clone();
+        let base = root.clone();
+        WalkBuilder::new(&root)
+            .hidden(!self.hidden)
+            .parents(self.ignore_files)
+            .ignore(self.ignore_files)
+            .git_ignore(self.ignore_files)
+            .git_global(self.ignore_files)
+            .git_exclude(self.ignore_files)
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(move |entry| {
+                entry.depth() == 0 || !matches(&exclude, &base, entry.path())
+            })
+            .build()
+            .filter_map(move |entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => return Some(Err(e)),
+                };
+                let is_file = entry.file_type().is_some_and(|t| t.is_file());
+                let included = entry.depth() == 0
+                    || include.is_empty()
+                    || matches(&include, &root, entry.path());
+                if is_file && included {
+                    Some(Ok(entry.into_path()))
+                } else {
+                    None
+                }
+            })
+    }
+}
+
+fn glob_set(globs: &[Glob]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(glob.clone());
+    }
+    builder.build()
+}
+
+/// root からの相対パスかファイル名が set にマッチすれば `true`
+fn matches(set: &GlobSet, root: &Path, path: &Path) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    set.is_match(relative) || path.file_name().is_some_and(|name| set.is_match(name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    fn tree(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("wordcount-walk-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in &["docs/api", ".git", ".hidden", "vendor"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in &[
+            "README.md",
+            "docs/guide.md",
+            "docs/api/index.md",
+            "docs/notes.txt",
+            "docs/build.log",
+            ".hidden/secret.md",
+            "vendor/lib.md",
+        ] {
+            fs::write(root.join(file), "text").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        root
+    }
+
+    fn relative(walk: &Walk, root: &Path) -> Vec<String> {
+        walk.files(root)
+            .map(|path| {
+                let path = path.unwrap();
+                let relative = path.strip_prefix(root).unwrap();
+                relative.to_string_lossy().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn skips_hidden_and_ignored_files() {
+        let root = tree("default");
+        assert_eq!(
+            relative(&Walk::new(), &root),
+            [
+                "README.md",
+                "docs/api/index.md",
+                "docs/guide.md",
+                "docs/notes.txt",
+                "vendor/lib.md"
+            ]
+        );
+        let all = Walk::new().hidden(true).ignore_files(false);
+        let files = relative(&all, &root);
+        assert!(files.contains(&".hidden/secret.md".to_string()));
+        assert!(files.contains(&"docs/build.log".to_string()));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn globs_match_relative_path_or_name() {
+        let root = tree("globs");
+        let walk = Walk::new()
+            .include("*.md")
+            .unwrap()
+            .exclude("vendor")
+            .unwrap()
+            .exclude("docs/api/**")
+            .unwrap();
+        assert_eq!(relative(&walk, &root), ["README.md", "docs/guide.md"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn detects_nul_in_head() {
+        assert!(is_binary(b"abc\0def"));
+        let mut late = vec![b'a'; BINARY_CHECK_LEN];
+        late.push(0);
+        assert!(!is_binary(&late));
+    }
+}
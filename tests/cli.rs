@@ -0,0 +1,241 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+const NAME: &str = env!("CARGO_PKG_NAME");
+
+fn wordcount() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_fhiroki-bicycle-book-wordcount"))
+}
+
+/// args で実行し、stdin を標準入力に渡す
+fn run(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = wordcount()
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // 入力を読まずに終了した場合の書き込みの失敗は無視する
+    let _ = child.stdin.take().unwrap().write_all(stdin);
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+fn temp(name: &str, content: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("wordcount-cli-{}-{}", name, std::process::id()));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+/// 一行ずつの診断メッセージのすべてにプログラム名が付いていることを確かめる
+fn assert_prefixed(output: &Output) {
+    let stderr = stderr(output);
+    assert!(!stderr.is_empty());
+    for line in stderr.lines() {
+        assert!(line.starts_with(&format!("{}: ", NAME)), "{:?}", line);
+    }
+}
+
+#[test]
+fn counts_stdin() {
+    let output = run(&["--sort", "key"], b"b a\nb\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "a\t1\nb\t2\n");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn help_and_version_go_to_stdout() {
+    for flag in &["--help", "--version"] {
+        let output = run(&[flag], b"");
+        assert_eq!(output.status.code(), Some(0), "{}", flag);
+        assert!(!output.stdout.is_empty(), "{}", flag);
+        assert!(output.stderr.is_empty(), "{}", flag);
+    }
+    let output = run(&["cooccur", "--help"], b"");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("--window"));
+}
+
+#[test]
+fn usage_errors_exit_64() {
+    let dict = temp("usage-dict", "語,0,0,1,名詞\n".as_bytes());
+    let dict = dict.to_str().unwrap();
+    let cases = vec![
+        vec!["--no-such-flag"],
+        vec!["--ngram", "0"],
+        vec!["--ngram", "2", "--mode", "line"],
+        vec!["--stem", "en", "--mode", "char"],
+        vec!["--dict", dict],
+        vec!["--pos", "名詞", "--dict", dict],
+        vec!["--ngram-reset"],
+        vec!["--cross-lines"],
+        vec!["--pattern", "("],
+        vec!["cooccur", "--window", "0", "--vocab", "v", "--triples", "t"],
+        vec!["cooccur", "--window", "2"],
+        vec![
+            "cooccur",
+            "--window",
+            "2",
+            "--vocab",
+            "v",
+            "--triples",
+            "t",
+            "--top",
+            "3",
+        ],
+    ];
+    for args in cases {
+        let output = run(&args, b"a\n");
+        assert_eq!(output.status.code(), Some(64), "{:?}", args);
+        assert!(output.stdout.is_empty(), "{:?}", args);
+        // clap や正規表現のエラーは複数行になるので、先頭だけを確かめる
+        assert!(
+            stderr(&output).starts_with(&format!("{}: ", NAME)),
+            "{:?}",
+            args
+        );
+    }
+    fs::remove_file(dict).unwrap();
+}
+
+#[test]
+fn invalid_input_exits_65() {
+    let output = run(&[], b"ok\n\xff\n");
+    assert_eq!(output.status.code(), Some(65));
+    assert_prefixed(&output);
+
+    let output = run(&["--decode", "lossy"], b"ok\n\xff\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_prefixed(&output);
+}
+
+#[test]
+fn unreadable_files_exit_66_and_others_are_counted() {
+    let file = temp("missing", b"x y\n");
+    let missing = file.with_extension("missing");
+    let output = run(&[file.to_str().unwrap(), missing.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(66));
+    assert_eq!(stdout(&output), "x\t1\ny\t1\n");
+    assert_prefixed(&output);
+
+    let dir = std::env::temp_dir();
+    let output = run(&[dir.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(66));
+    assert!(stderr(&output).contains("--recursive"));
+    fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn binary_files_are_skipped_with_a_notice() {
+    let binary = temp("binary", b"a\0b\n");
+    let output = run(&[binary.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(stderr(&output).contains("skipping binary file"));
+    assert_prefixed(&output);
+    fs::remove_file(binary).unwrap();
+}
+
+#[test]
+fn alias_rewrites_are_reported() {
+    let aliases = temp("aliases", b"colour\tcolor\n");
+    let output = run(&["--aliases", aliases.to_str().unwrap()], b"colour color\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "color\t2\n");
+    assert!(stderr(&output).contains("colour -> color: 1"));
+    assert_prefixed(&output);
+    fs::remove_file(aliases).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn counts_pipes_given_as_files() {
+    for args in &[
+        &["/dev/stdin"][..],
+        &["-j", "2", "--mmap", "/dev/stdin"][..],
+    ] {
+        let output = run(args, b"hi there\nhi\n");
+        assert_eq!(
+            output.status.code(),
+            Some(0),
+            "{:?}: {}",
+            args,
+            stderr(&output)
+        );
+        assert_eq!(stdout(&output), "hi\t2\nthere\t1\n");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn closed_stdout_is_not_an_error() {
+    let input: String = (0..100_000).map(|i| format!("w{}\n", i)).collect();
+    let input = temp("broken-pipe", input.as_bytes());
+    let mut child = wordcount()
+        .arg(&input)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty(), "{}", stderr(&output));
+    fs::remove_file(input).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn write_errors_exit_74() {
+    let output = wordcount()
+        .args(["--format", "json", "-"])
+        .stdin(Stdio::null())
+        .stdout(
+            fs::OpenOptions::new()
+                .write(true)
+                .open("/dev/full")
+                .unwrap(),
+        )
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(74));
+    assert_prefixed(&output);
+}
+
+#[test]
+fn cooccur_writes_vocab_and_triples() {
+    let vocab = temp("vocab", b"");
+    let triples = temp("triples", b"");
+    let output = run(
+        &[
+            "cooccur",
+            "--window",
+            "1",
+            "--vocab",
+            vocab.to_str().unwrap(),
+            "--triples",
+            triples.to_str().unwrap(),
+        ],
+        b"x y y\n",
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(fs::read_to_string(&vocab).unwrap(), "y\t2\nx\t1\n");
+    assert_eq!(
+        fs::read_to_string(&triples).unwrap(),
+        "1\t1\t2\n1\t2\t1\n2\t1\t1\n"
+    );
+    fs::remove_file(vocab).unwrap();
+    fs::remove_file(triples).unwrap();
+}